@@ -0,0 +1,278 @@
+use std::fmt;
+
+use crate::proc::Context;
+use crate::trap::TrapFrame;
+
+// What the process model needs to know about a cpu: the registers swtch
+// saves (Context) and the user registers a trap saves (TrapFrame).
+// Proc and ProcessTable are generic over it and default to X86, the
+// 32-bit xv6 the system calls, exec and the scheduler are written for.
+pub trait Arch: fmt::Debug + Default + 'static {
+    const NAME: &'static str;
+    type Context: fmt::Debug + Clone + Copy + Default;
+    type TrapFrame: fmt::Debug + Clone + Copy + Default;
+    const WORD: usize; // bytes in a register
+    // x86 builds the trap frame on the kernel stack; xv6-riscv gives it a page
+    const TRAPFRAME_ON_KSTACK: bool = true;
+
+    // allocproc's context: resume at pc on the kernel stack at sp
+    fn init_context(ctx: &mut Self::Context, pc: u64, sp: u64);
+
+    // the register a system call returns its result in
+    fn set_return(tf: &mut Self::TrapFrame, value: u64);
+    fn return_value(tf: &Self::TrapFrame) -> u64;
+}
+
+// 32-bit x86, the original xv6
+#[derive(Debug, Clone, Copy, Default)]
+pub struct X86;
+
+impl Arch for X86 {
+    const NAME: &'static str = "x86";
+    type Context = Context;
+    type TrapFrame = TrapFrame;
+    const WORD: usize = 4;
+
+    fn init_context(ctx: &mut Context, pc: u64, sp: u64) {
+        ctx.eip = pc as u32;
+        ctx.esp = sp as u32;
+    }
+
+    fn set_return(tf: &mut TrapFrame, value: u64) {
+        tf.eax = value as u32;
+    }
+
+    fn return_value(tf: &TrapFrame) -> u64 {
+        tf.eax as u64
+    }
+}
+
+// x86-64, as in the 64-bit xv6 ports: swtch saves the System V
+// callee-saved registers plus the return address
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct Context64 {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbx: u64,
+    pub rbp: u64,
+    pub rip: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct TrapFrame64 {
+    pub rax: u64, // syscall number in, return value out
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rbp: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub trapno: u64,
+    pub err: u64,
+
+    // pushed by the hardware
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct X86_64;
+
+impl Arch for X86_64 {
+    const NAME: &'static str = "x86-64";
+    type Context = Context64;
+    type TrapFrame = TrapFrame64;
+    const WORD: usize = 8;
+
+    // Context64 has no stack pointer field: swtch pushes the context onto
+    // the kernel stack, so the pointer to it is the saved rsp, and
+    // allocproc has already placed it at sp. Only the pc is stored.
+    fn init_context(ctx: &mut Context64, pc: u64, _sp: u64) {
+        ctx.rip = pc;
+    }
+
+    fn set_return(tf: &mut TrapFrame64, value: u64) {
+        tf.rax = value;
+    }
+
+    fn return_value(tf: &TrapFrame64) -> u64 {
+        tf.rax
+    }
+}
+
+// xv6-riscv's struct context: the registers swtch.S saves
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct RiscvContext {
+    pub ra: u64,
+    pub sp: u64,
+
+    // callee-saved
+    pub s0: u64,
+    pub s1: u64,
+    pub s2: u64,
+    pub s3: u64,
+    pub s4: u64,
+    pub s5: u64,
+    pub s6: u64,
+    pub s7: u64,
+    pub s8: u64,
+    pub s9: u64,
+    pub s10: u64,
+    pub s11: u64,
+}
+
+// xv6-riscv's struct trapframe: the page trampoline.S saves user
+// registers into, with the kernel values uservec needs up front
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct RiscvTrapFrame {
+    pub kernel_satp: u64,   // kernel page table
+    pub kernel_sp: u64,     // top of process's kernel stack
+    pub kernel_trap: u64,   // usertrap()
+    pub epc: u64,           // saved user program counter
+    pub kernel_hartid: u64, // saved kernel tp
+    pub ra: u64,
+    pub sp: u64,
+    pub gp: u64,
+    pub tp: u64,
+    pub t0: u64,
+    pub t1: u64,
+    pub t2: u64,
+    pub s0: u64,
+    pub s1: u64,
+    pub a0: u64, // first argument, return value out
+    pub a1: u64,
+    pub a2: u64,
+    pub a3: u64,
+    pub a4: u64,
+    pub a5: u64,
+    pub a6: u64,
+    pub a7: u64, // syscall number
+    pub s2: u64,
+    pub s3: u64,
+    pub s4: u64,
+    pub s5: u64,
+    pub s6: u64,
+    pub s7: u64,
+    pub s8: u64,
+    pub s9: u64,
+    pub s10: u64,
+    pub s11: u64,
+    pub t3: u64,
+    pub t4: u64,
+    pub t5: u64,
+    pub t6: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Riscv;
+
+impl Arch for Riscv {
+    const NAME: &'static str = "riscv";
+    type Context = RiscvContext;
+    type TrapFrame = RiscvTrapFrame;
+    const WORD: usize = 8;
+    const TRAPFRAME_ON_KSTACK: bool = false;
+
+    fn init_context(ctx: &mut RiscvContext, pc: u64, sp: u64) {
+        ctx.ra = pc;
+        ctx.sp = sp;
+    }
+
+    fn set_return(tf: &mut RiscvTrapFrame, value: u64) {
+        tf.a0 = value;
+    }
+
+    fn return_value(tf: &RiscvTrapFrame) -> u64 {
+        tf.a0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proc::ProcState;
+    use crate::ptable::ProcessTable;
+    use std::mem::{offset_of, size_of};
+
+    #[test]
+    fn test_x86_layouts() {
+        assert_eq!(size_of::<Context>(), 32);
+        assert_eq!(offset_of!(Context, eip), 0);
+        assert_eq!(offset_of!(Context, esp), 4);
+        assert_eq!(offset_of!(Context, ebp), 28);
+
+        // sizeof(struct trapframe) in xv6
+        assert_eq!(size_of::<TrapFrame>(), 76);
+        assert_eq!(offset_of!(TrapFrame, eax), 28);
+        assert_eq!(offset_of!(TrapFrame, gs), 32);
+        assert_eq!(offset_of!(TrapFrame, trapno), 48);
+        assert_eq!(offset_of!(TrapFrame, eip), 56);
+        assert_eq!(offset_of!(TrapFrame, esp), 68);
+        assert_eq!(offset_of!(TrapFrame, ss), 72);
+    }
+
+    #[test]
+    fn test_x86_64_layouts() {
+        assert_eq!(size_of::<Context64>(), 56);
+        assert_eq!(offset_of!(Context64, rbx), 32);
+        assert_eq!(offset_of!(Context64, rip), 48);
+
+        assert_eq!(size_of::<TrapFrame64>(), 176);
+        assert_eq!(offset_of!(TrapFrame64, r15), 112);
+        assert_eq!(offset_of!(TrapFrame64, trapno), 120);
+        assert_eq!(offset_of!(TrapFrame64, rip), 136);
+        assert_eq!(offset_of!(TrapFrame64, rsp), 160);
+    }
+
+    #[test]
+    fn test_riscv_layouts() {
+        assert_eq!(size_of::<RiscvContext>(), 112);
+        assert_eq!(offset_of!(RiscvContext, sp), 8);
+        assert_eq!(offset_of!(RiscvContext, s0), 16);
+        assert_eq!(offset_of!(RiscvContext, s11), 104);
+
+        // the offsets commented in xv6-riscv's proc.h, which trampoline.S uses
+        assert_eq!(size_of::<RiscvTrapFrame>(), 288);
+        assert_eq!(offset_of!(RiscvTrapFrame, epc), 24);
+        assert_eq!(offset_of!(RiscvTrapFrame, ra), 40);
+        assert_eq!(offset_of!(RiscvTrapFrame, s1), 104);
+        assert_eq!(offset_of!(RiscvTrapFrame, a0), 112);
+        assert_eq!(offset_of!(RiscvTrapFrame, a7), 168);
+        assert_eq!(offset_of!(RiscvTrapFrame, s2), 176);
+        assert_eq!(offset_of!(RiscvTrapFrame, t6), 280);
+    }
+
+    #[test]
+    fn test_riscv_fork_returns_zero_in_a0() {
+        let mut ptable = ProcessTable::<Riscv>::for_arch(4, 16);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        ptable.find_process(init).unwrap().tf.a0 = 7;
+        ptable.find_process(init).unwrap().context.s11 = 0x5eed;
+
+        let child = ptable.fork(init).unwrap();
+        assert_eq!(Riscv::return_value(&ptable.get(child).unwrap().tf), 0);
+        assert_eq!(ptable.get(child).unwrap().context.s11, 0);
+        ptable.run(child).unwrap();
+        ptable.exit(child, 3).unwrap();
+        assert_eq!(ptable.get(child).unwrap().state(), ProcState::Zombie);
+        assert_eq!(ptable.wait(init), Ok(Some((child, 3))));
+    }
+}
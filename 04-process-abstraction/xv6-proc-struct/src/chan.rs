@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+// What a sleeping process is waiting for. xv6 sleeps on an arbitrary
+// kernel address; here each kind of wait gets its own variant so two
+// unrelated waits can never collide on the same channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Chan {
+    Proc(i32),     // a parent in wait() sleeps on its own pid
+    Ticks,         // sleep(n) waits for the timer
+    Disk(u32),     // a buffer waiting on a disk block
+    Pipe(usize),   // reader or writer blocked on a pipe
+    Object(usize), // any other kernel object
+}
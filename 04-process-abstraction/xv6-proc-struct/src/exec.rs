@@ -0,0 +1,264 @@
+use crate::proc::ProcError;
+use crate::ptable::ProcessTable;
+use crate::trap::TrapFrame;
+use crate::vm::{PGSIZE, pgroundup};
+
+pub const MAXARG: usize = 32; // max exec arguments
+
+// a program image to load: the bytes go at virtual address 0 and
+// execution starts at entry (what xv6 reads out of the ELF headers)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub path: String,
+    pub image: Vec<u8>,
+    pub entry: u32,
+}
+
+impl Program {
+    pub fn new(path: &str, image: &[u8], entry: u32) -> Self {
+        Program {
+            path: path.to_string(),
+            image: image.to_vec(),
+            entry,
+        }
+    }
+
+    // the last path component, used as the process name
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+}
+
+impl ProcessTable {
+    // make a program available to the exec system call under its path
+    pub fn install(&mut self, program: Program) {
+        self.programs.insert(program.path.clone(), program);
+    }
+
+    pub fn program(&self, path: &str) -> Option<&Program> {
+        self.programs.get(path)
+    }
+
+    // Replace a process's memory with program, following xv6's exec.c:
+    // load the image, add a guard page and a stack page, push the argv
+    // strings and the argc/argv frame, then point the trap frame at the
+    // entry. Nothing about the process changes unless every step succeeds.
+    pub fn exec(&mut self, pid: i32, program: &Program, argv: &[&str]) -> Result<(), ProcError> {
+        if argv.len() > MAXARG {
+            return Err(ProcError::TooManyArgs);
+        }
+        self.get(pid).ok_or(ProcError::NoSuchProcess(pid))?;
+
+        let mut pages = Vec::new();
+        let image_sz = pgroundup(program.image.len());
+        let sz = image_sz + 2 * PGSIZE;
+        if let Err(e) = self.kmem.allocuvm(&mut pages, sz) {
+            self.kmem.freevm(pages);
+            return Err(e);
+        }
+        self.kmem.write(&pages, 0, &program.image);
+
+        // the page below the stack stays as an (unenforced) guard
+        let mut sp = sz;
+        let mut ustack = vec![0u32; 3 + argv.len() + 1];
+        for (i, arg) in argv.iter().enumerate() {
+            let bytes = arg.as_bytes();
+            if bytes.len() + 1 > sp - (image_sz + PGSIZE) {
+                self.kmem.freevm(pages);
+                return Err(ProcError::TooManyArgs);
+            }
+            sp = (sp - (bytes.len() + 1)) & !3;
+            self.kmem.write(&pages, sp, bytes);
+            self.kmem.write(&pages, sp + bytes.len(), &[0]);
+            ustack[3 + i] = sp as u32;
+        }
+        ustack[3 + argv.len()] = 0;
+
+        ustack[0] = 0xffffffff; // fake return PC
+        ustack[1] = argv.len() as u32; // argc
+        ustack[2] = (sp - (argv.len() + 1) * 4) as u32; // argv pointer
+        let frame: Vec<u8> = ustack.iter().flat_map(|w| w.to_le_bytes()).collect();
+        if frame.len() > sp - (image_sz + PGSIZE) {
+            self.kmem.freevm(pages);
+            return Err(ProcError::TooManyArgs);
+        }
+        sp -= frame.len();
+        self.kmem.write(&pages, sp, &frame);
+
+        // commit to the user image
+        let proc = self
+            .find_process(pid)
+            .ok_or(ProcError::NoSuchProcess(pid))?;
+        let old = std::mem::replace(&mut proc.mem, pages);
+        proc.sz = sz as u32;
+        proc.name = program.name().to_string();
+        proc.tf = TrapFrame {
+            eip: program.entry,
+            esp: sp as u32,
+            ..TrapFrame::default()
+        };
+        self.kmem.freevm(old);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proc::ProcState;
+    use crate::syscall::{SYS_EXEC, SYS_EXIT, SYS_FORK, SYS_WAIT};
+
+    fn read_u32(ptable: &ProcessTable, pid: i32, va: u32) -> u32 {
+        let b = ptable.copyin(pid, va, 4).unwrap();
+        u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+    }
+
+    fn read_str(ptable: &ProcessTable, pid: i32, va: u32) -> String {
+        let mut s = Vec::new();
+        for a in va.. {
+            match ptable.copyin(pid, a, 1).unwrap()[0] {
+                0 => break,
+                b => s.push(b),
+            }
+        }
+        String::from_utf8(s).unwrap()
+    }
+
+    fn running_init() -> (ProcessTable, i32) {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        (ptable, init)
+    }
+
+    #[test]
+    fn test_exec_builds_argv_stack() {
+        let (mut ptable, init) = running_init();
+        let echo = Program::new("/bin/echo", b"\x90\x90\xc3", 0x2);
+        ptable
+            .exec(init, &echo, &["echo", "hello", "world"])
+            .unwrap();
+
+        let proc = ptable.get(init).unwrap();
+        assert_eq!(proc.name(), "echo");
+        assert_eq!(proc.sz(), 3 * PGSIZE as u32);
+        assert_eq!(proc.tf.eip, 0x2);
+        assert_eq!(ptable.copyin(init, 0, 3).unwrap(), b"\x90\x90\xc3");
+
+        // esp -> [fake pc, argc, argv, argv[0], argv[1], argv[2], 0]
+        let sp = proc.tf.esp;
+        assert_eq!(sp % 4, 0);
+        assert_eq!(read_u32(&ptable, init, sp), 0xffffffff);
+        assert_eq!(read_u32(&ptable, init, sp + 4), 3);
+        let argv = read_u32(&ptable, init, sp + 8);
+        assert_eq!(argv, sp + 12);
+        let args: Vec<_> = (0..3)
+            .map(|i| read_str(&ptable, init, read_u32(&ptable, init, argv + 4 * i)))
+            .collect();
+        assert_eq!(args, vec!["echo", "hello", "world"]);
+        assert_eq!(read_u32(&ptable, init, argv + 12), 0);
+    }
+
+    #[test]
+    fn test_exec_replaces_image_and_registers() {
+        let (mut ptable, init) = running_init();
+        ptable.growproc(init, 5 * PGSIZE as i64).unwrap();
+        ptable.find_process(init).unwrap().tf.eax = 7;
+
+        ptable
+            .exec(init, &Program::new("sh", &[0; 10], 0), &[])
+            .unwrap();
+        // old image freed, new one is text + guard + stack
+        assert_eq!(ptable.kmem().used_pages(), 3);
+        let proc = ptable.get(init).unwrap();
+        assert_eq!(proc.tf.eax, 0);
+        assert_eq!(proc.state(), ProcState::Running);
+    }
+
+    // exec only swaps the user side; the kernel context allocproc set up
+    // (forkret on this process's kernel stack) is what swtch resumes
+    #[test]
+    fn test_exec_keeps_kernel_context() {
+        let (mut ptable, init) = running_init();
+        let regs = |ptable: &ProcessTable, pid| {
+            let c = ptable.get(pid).unwrap().context;
+            (c.eip, c.esp)
+        };
+        let before = regs(&ptable, init);
+        assert_eq!(before.0 as u64, crate::kstack::FORKRET);
+
+        ptable
+            .exec(init, &Program::new("sh", b"sh", 0x4), &["sh"])
+            .unwrap();
+        assert_eq!(regs(&ptable, init), before);
+    }
+
+    #[test]
+    fn test_failed_exec_keeps_old_image() {
+        let mut ptable = ProcessTable::with_memory(2, 4);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        ptable
+            .exec(init, &Program::new("init", &[1; 8], 0), &["init"])
+            .unwrap();
+
+        let huge = Program::new("big", &vec![0; 4 * PGSIZE], 0);
+        assert_eq!(ptable.exec(init, &huge, &[]), Err(ProcError::OutOfMemory));
+        let too_many = vec!["x"; MAXARG + 1];
+        assert_eq!(
+            ptable.exec(init, &Program::new("sh", &[], 0), &too_many),
+            Err(ProcError::TooManyArgs)
+        );
+
+        assert_eq!(ptable.get(init).unwrap().name(), "init");
+        assert_eq!(ptable.copyin(init, 0, 8).unwrap(), vec![1; 8]);
+        assert_eq!(ptable.kmem().used_pages(), 3);
+    }
+
+    #[test]
+    fn test_fork_exec_wait_end_to_end() {
+        let (mut ptable, init) = running_init();
+        ptable.install(Program::new("/bin/ls", b"ls code", 0x10));
+        ptable
+            .exec(init, &Program::new("/init", b"init", 0), &["init"])
+            .unwrap();
+
+        // put "/bin/ls", "ls", "-l" and a null-terminated argv array in init's memory
+        ptable.copyout(init, 0x100, b"/bin/ls\0ls\0-l\0").unwrap();
+        let argv: Vec<u8> = [0x108u32, 0x10b, 0]
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect();
+        ptable.copyout(init, 0x120, &argv).unwrap();
+
+        let child = ptable.invoke(init, SYS_FORK, &[]).unwrap().unwrap();
+        assert_eq!(ptable.invoke(init, SYS_WAIT, &[0]), Ok(None));
+
+        ptable.run(child).unwrap();
+        assert_eq!(ptable.invoke(child, SYS_EXEC, &[0x100, 0x120]), Ok(Some(0)));
+        let proc = ptable.get(child).unwrap();
+        assert_eq!(proc.name(), "ls");
+        assert_eq!(proc.tf.eip, 0x10);
+        assert_eq!(read_u32(&ptable, child, proc.tf.esp + 4), 2);
+        assert_eq!(ptable.copyin(child, 0, 7).unwrap(), b"ls code");
+        assert_eq!(ptable.invoke(child, SYS_EXIT, &[0]), Ok(None));
+
+        ptable.run(init).unwrap();
+        assert_eq!(ptable.invoke(init, SYS_WAIT, &[0]), Ok(Some(child)));
+        assert_eq!(ptable.get(init).unwrap().name(), "init");
+    }
+
+    #[test]
+    fn test_exec_unknown_path() {
+        let (mut ptable, init) = running_init();
+        ptable
+            .exec(init, &Program::new("/init", b"", 0), &[])
+            .unwrap();
+        ptable.copyout(init, 0x10, b"/nope\0").unwrap();
+        ptable.copyout(init, 0x20, &[0; 4]).unwrap();
+        assert_eq!(
+            ptable.invoke(init, SYS_EXEC, &[0x10, 0x20]),
+            Err(ProcError::FileNotFound)
+        );
+    }
+}
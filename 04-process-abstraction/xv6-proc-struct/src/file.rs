@@ -0,0 +1,382 @@
+use crate::arch::Arch;
+use crate::fs::{FileSystem, Inode, ROOTINO};
+use crate::proc::{FileHandle, InodeHandle, NOFILE, ProcError};
+use crate::ptable::ProcessTable;
+
+pub const NFILE: usize = 100; // open files per system
+
+// open() flags, same values as xv6's fcntl.h
+pub const O_RDONLY: u32 = 0x000;
+pub const O_WRONLY: u32 = 0x001;
+pub const O_RDWR: u32 = 0x002;
+pub const O_CREATE: u32 = 0x200;
+pub const O_TRUNC: u32 = 0x400;
+
+// an entry in the system-wide open file table; every descriptor that
+// refers to it (after dup or fork) shares the same offset
+#[derive(Debug, Clone)]
+pub struct File {
+    path: String, // as given to open, for debugging
+    ip: InodeHandle,
+    readable: bool,
+    writable: bool,
+    off: usize,
+    refs: usize,
+}
+
+impl File {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn inode(&self) -> InodeHandle {
+        self.ip
+    }
+
+    pub fn offset(&self) -> usize {
+        self.off
+    }
+
+    pub fn refs(&self) -> usize {
+        self.refs
+    }
+}
+
+// xv6's ftable, plus the filesystem its files live in
+#[derive(Debug)]
+pub struct FileTable {
+    files: Vec<Option<File>>, // indexed by FileHandle
+    pub(crate) fs: FileSystem,
+}
+
+impl Default for FileTable {
+    fn default() -> Self {
+        FileTable::new()
+    }
+}
+
+impl FileTable {
+    pub fn new() -> Self {
+        FileTable {
+            files: vec![None; NFILE],
+            fs: FileSystem::new(),
+        }
+    }
+
+    pub fn get(&self, fh: FileHandle) -> Option<&File> {
+        self.files.get(fh)?.as_ref()
+    }
+
+    // a regular file's bytes, by path from the root
+    pub fn contents(&self, path: &str) -> Option<&[u8]> {
+        let ip = self.fs.namei(ROOTINO, path).ok()?;
+        match self.fs.inode(ip)? {
+            Inode::File(data) => Some(data),
+            Inode::Dir(_) => None,
+        }
+    }
+
+    // open path relative to cwd and take a free slot for it
+    // (filealloc + sys_open)
+    fn open(&mut self, cwd: InodeHandle, path: &str, flags: u32) -> Result<FileHandle, ProcError> {
+        let fh = self
+            .files
+            .iter()
+            .position(|f| f.is_none())
+            .ok_or(ProcError::FileTableFull)?;
+        let writable = flags & (O_WRONLY | O_RDWR) != 0;
+        let ip = if flags & O_CREATE != 0 {
+            self.fs.create(cwd, path, false)?
+        } else {
+            self.fs.namei(cwd, path)?
+        };
+        // directories can only be opened for reading
+        match self.fs.inode_mut(ip) {
+            Some(Inode::Dir(_)) if writable => return Err(ProcError::IsADirectory),
+            Some(Inode::File(data)) if flags & O_TRUNC != 0 => data.clear(),
+            _ => {}
+        }
+
+        self.files[fh] = Some(File {
+            path: path.to_string(),
+            ip,
+            readable: flags & O_WRONLY == 0,
+            writable,
+            off: 0,
+            refs: 1,
+        });
+        Ok(fh)
+    }
+
+    // another reference to the same open file (filedup)
+    fn dup(&mut self, fh: FileHandle) -> FileHandle {
+        if let Some(file) = self.files[fh].as_mut() {
+            file.refs += 1;
+        }
+        fh
+    }
+
+    // drop a reference; the slot is freed with the last one (fileclose)
+    fn close(&mut self, fh: FileHandle) {
+        if let Some(file) = self.files[fh].as_mut() {
+            file.refs -= 1;
+            if file.refs == 0 {
+                self.files[fh] = None;
+            }
+        }
+    }
+
+    fn read(&mut self, fh: FileHandle, n: usize) -> Option<Vec<u8>> {
+        let file = self.files[fh].as_mut().filter(|f| f.readable)?;
+        let dirents;
+        let data = match self.fs.inode(file.ip)? {
+            Inode::File(data) => data,
+            Inode::Dir(_) => {
+                dirents = self.fs.dirents(file.ip);
+                &dirents
+            }
+        };
+        let start = file.off.min(data.len());
+        let end = (start + n).min(data.len());
+        file.off = end;
+        Some(data[start..end].to_vec())
+    }
+
+    fn write(&mut self, fh: FileHandle, buf: &[u8]) -> Option<usize> {
+        let file = self.files[fh].as_mut().filter(|f| f.writable)?;
+        let Some(Inode::File(data)) = self.fs.inode_mut(file.ip) else {
+            return None;
+        };
+        let end = file.off + buf.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[file.off..end].copy_from_slice(buf);
+        file.off = end;
+        Some(buf.len())
+    }
+}
+
+// per-process descriptor operations, always handing out the lowest free fd
+impl<A: Arch> ProcessTable<A> {
+    fn fdalloc(&mut self, pid: i32, fh: FileHandle) -> Result<usize, ProcError> {
+        let proc = self
+            .find_process(pid)
+            .ok_or(ProcError::NoSuchProcess(pid))?;
+        let fd = proc
+            .ofile
+            .iter()
+            .position(|f| f.is_none())
+            .ok_or(ProcError::NoFreeFd(pid))?;
+        proc.ofile[fd] = Some(fh);
+        Ok(fd)
+    }
+
+    fn file_handle(&self, pid: i32, fd: usize) -> Result<FileHandle, ProcError> {
+        let proc = self.get(pid).ok_or(ProcError::NoSuchProcess(pid))?;
+        proc.ofile
+            .get(fd)
+            .copied()
+            .flatten()
+            .ok_or(ProcError::BadFd { pid, fd })
+    }
+
+    pub fn open(&mut self, pid: i32, path: &str, flags: u32) -> Result<usize, ProcError> {
+        // check for a free descriptor first so a failed open leaks nothing
+        let proc = self.get(pid).ok_or(ProcError::NoSuchProcess(pid))?;
+        if proc.ofile.iter().all(|f| f.is_some()) {
+            return Err(ProcError::NoFreeFd(pid));
+        }
+        let cwd = proc.cwd.unwrap_or(ROOTINO);
+        let fh = self.ftable.open(cwd, path, flags)?;
+        self.fdalloc(pid, fh)
+    }
+
+    pub fn close(&mut self, pid: i32, fd: usize) -> Result<(), ProcError> {
+        let fh = self.file_handle(pid, fd)?;
+        self.ftable.close(fh);
+        if let Some(proc) = self.find_process(pid) {
+            proc.ofile[fd] = None;
+        }
+        Ok(())
+    }
+
+    pub fn dup(&mut self, pid: i32, fd: usize) -> Result<usize, ProcError> {
+        let fh = self.file_handle(pid, fd)?;
+        let new_fd = self.fdalloc(pid, fh)?;
+        self.ftable.dup(fh);
+        Ok(new_fd)
+    }
+
+    // make new_fd refer to the same file as old_fd, closing new_fd first
+    pub fn dup2(&mut self, pid: i32, old_fd: usize, new_fd: usize) -> Result<usize, ProcError> {
+        let fh = self.file_handle(pid, old_fd)?;
+        if new_fd >= NOFILE {
+            return Err(ProcError::BadFd { pid, fd: new_fd });
+        }
+        if old_fd == new_fd {
+            return Ok(new_fd);
+        }
+        if self.file_handle(pid, new_fd).is_ok() {
+            self.close(pid, new_fd)?;
+        }
+        self.ftable.dup(fh);
+        if let Some(proc) = self.find_process(pid) {
+            proc.ofile[new_fd] = Some(fh);
+        }
+        Ok(new_fd)
+    }
+
+    pub fn read(&mut self, pid: i32, fd: usize, n: usize) -> Result<Vec<u8>, ProcError> {
+        let fh = self.file_handle(pid, fd)?;
+        self.ftable.read(fh, n).ok_or(ProcError::BadFd { pid, fd })
+    }
+
+    pub fn write(&mut self, pid: i32, fd: usize, buf: &[u8]) -> Result<usize, ProcError> {
+        let fh = self.file_handle(pid, fd)?;
+        self.ftable
+            .write(fh, buf)
+            .ok_or(ProcError::BadFd { pid, fd })
+    }
+
+    pub fn ftable(&self) -> &FileTable {
+        &self.ftable
+    }
+
+    // fork: the child's descriptors share the parent's open files
+    pub(crate) fn dup_ofiles(&mut self, ofile: &[Option<FileHandle>; NOFILE]) {
+        for fh in ofile.iter().flatten() {
+            self.ftable.dup(*fh);
+        }
+    }
+
+    // exit: close every descriptor the process still holds
+    pub(crate) fn close_ofiles(&mut self, pid: i32) {
+        let open: Vec<usize> = match self.get(pid) {
+            Some(proc) => (0..NOFILE).filter(|&fd| proc.ofile[fd].is_some()).collect(),
+            None => return,
+        };
+        for fd in open {
+            let _ = self.close(pid, fd);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn running_init() -> (ProcessTable, i32) {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        (ptable, init)
+    }
+
+    #[test]
+    fn test_lowest_free_descriptor() {
+        let (mut ptable, init) = running_init();
+        for expected in 0..3 {
+            let fd = ptable.open(init, "console", O_RDWR | O_CREATE).unwrap();
+            assert_eq!(fd, expected);
+        }
+
+        ptable.close(init, 1).unwrap();
+        assert_eq!(ptable.dup(init, 0), Ok(1));
+        assert_eq!(ptable.dup(init, 0), Ok(3));
+        assert_eq!(
+            ptable.close(init, 9),
+            Err(ProcError::BadFd { pid: init, fd: 9 })
+        );
+    }
+
+    #[test]
+    fn test_open_missing_file_needs_create() {
+        let (mut ptable, init) = running_init();
+        assert_eq!(
+            ptable.open(init, "nope", O_RDONLY),
+            Err(ProcError::FileNotFound)
+        );
+        assert_eq!(ptable.ftable().files.iter().flatten().count(), 0);
+    }
+
+    #[test]
+    fn test_redirect_stdout_like_file_descriptor_example() {
+        let (mut ptable, init) = running_init();
+        for _ in 0..3 {
+            ptable.open(init, "console", O_RDWR | O_CREATE).unwrap();
+        }
+
+        // child: close(1); open("tmp.txt") lands on fd 1, so "stdout" goes there
+        let child = ptable.fork(init).unwrap();
+        ptable.yield_cpu(init).unwrap();
+        ptable.run(child).unwrap();
+        ptable.close(child, 1).unwrap();
+        let fd = ptable
+            .open(child, "tmp.txt", O_CREATE | O_WRONLY | O_TRUNC)
+            .unwrap();
+        assert_eq!(fd, 1);
+        ptable.write(child, 1, b"wc output\n").unwrap();
+
+        // the parent's stdout still points at the console
+        ptable.write(init, 1, b"parent\n").unwrap();
+        assert_eq!(
+            ptable.ftable().contents("tmp.txt"),
+            Some(&b"wc output\n"[..])
+        );
+        assert_eq!(ptable.ftable().contents("console"), Some(&b"parent\n"[..]));
+    }
+
+    #[test]
+    fn test_fork_shares_offsets() {
+        let (mut ptable, init) = running_init();
+        let fd = ptable
+            .open(init, "shared_file.txt", O_CREATE | O_WRONLY)
+            .unwrap();
+        let child = ptable.fork(init).unwrap();
+        let fh = ptable.get(child).unwrap().ofile[fd].unwrap();
+        assert_eq!(ptable.ftable().get(fh).unwrap().refs(), 2);
+
+        // writes from both processes append instead of overwriting
+        ptable.write(init, fd, b"parent\n").unwrap();
+        ptable.yield_cpu(init).unwrap();
+        ptable.run(child).unwrap();
+        ptable.write(child, fd, b"child\n").unwrap();
+        assert_eq!(
+            ptable.ftable().contents("shared_file.txt"),
+            Some(&b"parent\nchild\n"[..])
+        );
+        assert_eq!(ptable.ftable().get(fh).unwrap().offset(), 13);
+
+        // exit drops the child's reference, the parent's stays open
+        ptable.exit(child, 0).unwrap();
+        assert_eq!(ptable.ftable().get(fh).unwrap().refs(), 1);
+        ptable.run(init).unwrap();
+        ptable.close(init, fd).unwrap();
+        assert!(ptable.ftable().get(fh).is_none());
+    }
+
+    #[test]
+    fn test_dup2_replaces_target() {
+        let (mut ptable, init) = running_init();
+        let a = ptable.open(init, "a", O_CREATE | O_RDWR).unwrap();
+        let b = ptable.open(init, "b", O_CREATE | O_RDWR).unwrap();
+        let b_fh = ptable.get(init).unwrap().ofile[b].unwrap();
+
+        assert_eq!(ptable.dup2(init, a, b), Ok(b));
+        assert!(ptable.ftable().get(b_fh).is_none());
+        ptable.write(init, b, b"via b").unwrap();
+        assert_eq!(ptable.ftable().contents("a"), Some(&b"via b"[..]));
+        assert_eq!(ptable.dup2(init, a, a), Ok(a));
+
+        // reading shares the same offset too
+        let r = ptable.open(init, "a", O_RDONLY).unwrap();
+        let r2 = ptable.dup(init, r).unwrap();
+        assert_eq!(ptable.read(init, r, 3).unwrap(), b"via");
+        assert_eq!(ptable.read(init, r2, 10).unwrap(), b" b");
+        assert_eq!(
+            ptable.write(init, r, b"x"),
+            Err(ProcError::BadFd { pid: init, fd: r })
+        );
+    }
+}
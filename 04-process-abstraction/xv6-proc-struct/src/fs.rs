@@ -0,0 +1,317 @@
+use std::collections::BTreeMap;
+
+use crate::arch::Arch;
+use crate::proc::{InodeHandle, ProcError};
+use crate::ptable::ProcessTable;
+
+pub const ROOTINO: InodeHandle = 1; // root i-number, as in xv6's fs.h
+pub const DIRSIZ: usize = 14; // name bytes in a directory entry
+
+// what an inode holds: a directory's entries (including "." and "..")
+// or a file's bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inode {
+    Dir(BTreeMap<String, InodeHandle>),
+    File(Vec<u8>),
+}
+
+// A minimal in-memory filesystem: an inode table indexed by i-number
+// (0 is never used, as in xv6) and path lookup like namei. Nothing is
+// ever freed, since there is no unlink.
+#[derive(Debug)]
+pub struct FileSystem {
+    inodes: Vec<Option<Inode>>,
+}
+
+impl Default for FileSystem {
+    fn default() -> Self {
+        FileSystem::new()
+    }
+}
+
+impl FileSystem {
+    pub fn new() -> Self {
+        let mut root = BTreeMap::new();
+        root.insert(".".to_string(), ROOTINO);
+        root.insert("..".to_string(), ROOTINO);
+        FileSystem {
+            inodes: vec![None, Some(Inode::Dir(root))],
+        }
+    }
+
+    pub fn inode(&self, inum: InodeHandle) -> Option<&Inode> {
+        self.inodes.get(inum)?.as_ref()
+    }
+
+    pub(crate) fn inode_mut(&mut self, inum: InodeHandle) -> Option<&mut Inode> {
+        self.inodes.get_mut(inum)?.as_mut()
+    }
+
+    pub fn is_dir(&self, inum: InodeHandle) -> bool {
+        matches!(self.inode(inum), Some(Inode::Dir(_)))
+    }
+
+    fn dirlookup(&self, dir: InodeHandle, name: &str) -> Result<InodeHandle, ProcError> {
+        match self.inode(dir) {
+            Some(Inode::Dir(entries)) => entries.get(name).copied().ok_or(ProcError::FileNotFound),
+            _ => Err(ProcError::NotADirectory),
+        }
+    }
+
+    // where path starts: the root for absolute paths, cwd otherwise
+    fn start(path: &str, cwd: InodeHandle) -> InodeHandle {
+        if path.starts_with('/') { ROOTINO } else { cwd }
+    }
+
+    // look up path relative to cwd
+    pub fn namei(&self, cwd: InodeHandle, path: &str) -> Result<InodeHandle, ProcError> {
+        path.split('/')
+            .filter(|name| !name.is_empty())
+            .try_fold(FileSystem::start(path, cwd), |ip, name| {
+                self.dirlookup(ip, name)
+            })
+    }
+
+    // the directory holding path's last element, and that element's name
+    fn nameiparent<'p>(
+        &self,
+        cwd: InodeHandle,
+        path: &'p str,
+    ) -> Result<(InodeHandle, &'p str), ProcError> {
+        let path = path.trim_end_matches('/');
+        let (dir, name) = match path.rfind('/') {
+            Some(i) => (&path[..i + 1], &path[i + 1..]),
+            None => ("", path),
+        };
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(ProcError::FileExists);
+        }
+        let parent = if dir.is_empty() {
+            cwd
+        } else {
+            self.namei(cwd, dir)?
+        };
+        if !self.is_dir(parent) {
+            return Err(ProcError::NotADirectory);
+        }
+        Ok((parent, name))
+    }
+
+    // Make a new file or directory at path, like xv6's create(). Asking
+    // for a file that already exists returns it, as open(O_CREATE) wants.
+    pub fn create(
+        &mut self,
+        cwd: InodeHandle,
+        path: &str,
+        dir: bool,
+    ) -> Result<InodeHandle, ProcError> {
+        let (parent, name) = self.nameiparent(cwd, path)?;
+        if let Ok(ip) = self.dirlookup(parent, name) {
+            return match self.inode(ip) {
+                Some(Inode::File(_)) if !dir => Ok(ip),
+                _ => Err(ProcError::FileExists),
+            };
+        }
+
+        let ip = self.inodes.len();
+        let inode = if dir {
+            let mut entries = BTreeMap::new();
+            entries.insert(".".to_string(), ip);
+            entries.insert("..".to_string(), parent);
+            Inode::Dir(entries)
+        } else {
+            Inode::File(Vec::new())
+        };
+        self.inodes.push(Some(inode));
+        if let Some(Inode::Dir(entries)) = self.inode_mut(parent) {
+            entries.insert(name.to_string(), ip);
+        }
+        Ok(ip)
+    }
+
+    // the absolute path of a directory, found by walking ".." up to the root
+    pub fn path(&self, dir: InodeHandle) -> Option<String> {
+        let mut names = Vec::new();
+        let mut ip = dir;
+        while ip != ROOTINO {
+            let parent = self.dirlookup(ip, "..").ok()?;
+            let Some(Inode::Dir(entries)) = self.inode(parent) else {
+                return None;
+            };
+            let (name, _) = entries
+                .iter()
+                .find(|&(name, &child)| child == ip && name != "." && name != "..")?;
+            names.push(name.clone());
+            ip = parent;
+        }
+        names.reverse();
+        Some(format!("/{}", names.join("/")))
+    }
+
+    // a directory read as xv6 dirents: a 2-byte i-number and a
+    // NUL-padded DIRSIZ-byte name each
+    pub(crate) fn dirents(&self, dir: InodeHandle) -> Vec<u8> {
+        let Some(Inode::Dir(entries)) = self.inode(dir) else {
+            return Vec::new();
+        };
+        let mut out = Vec::new();
+        for (name, &ip) in entries {
+            out.extend_from_slice(&(ip as u16).to_le_bytes());
+            let mut buf = [0u8; DIRSIZ];
+            let n = name.len().min(DIRSIZ);
+            buf[..n].copy_from_slice(&name.as_bytes()[..n]);
+            out.extend_from_slice(&buf);
+        }
+        out
+    }
+}
+
+impl<A: Arch> ProcessTable<A> {
+    // a process's working directory; the root if it never had one
+    pub fn cwd(&self, pid: i32) -> Result<InodeHandle, ProcError> {
+        let proc = self.get(pid).ok_or(ProcError::NoSuchProcess(pid))?;
+        Ok(proc.cwd.unwrap_or(ROOTINO))
+    }
+
+    pub fn getcwd(&self, pid: i32) -> Result<String, ProcError> {
+        let cwd = self.cwd(pid)?;
+        self.ftable.fs.path(cwd).ok_or(ProcError::FileNotFound)
+    }
+
+    pub fn chdir(&mut self, pid: i32, path: &str) -> Result<(), ProcError> {
+        let ip = self.ftable.fs.namei(self.cwd(pid)?, path)?;
+        if !self.ftable.fs.is_dir(ip) {
+            return Err(ProcError::NotADirectory);
+        }
+        if let Some(proc) = self.find_process(pid) {
+            proc.cwd = Some(ip);
+        }
+        Ok(())
+    }
+
+    pub fn mkdir(&mut self, pid: i32, path: &str) -> Result<(), ProcError> {
+        let cwd = self.cwd(pid)?;
+        self.ftable.fs.create(cwd, path, true).map(|_| ())
+    }
+
+    pub fn fs(&self) -> &FileSystem {
+        &self.ftable.fs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::{O_CREATE, O_RDONLY, O_RDWR, O_WRONLY};
+    use crate::syscall::{SYS_CHDIR, SYS_MKDIR, SYS_OPEN, SYS_WRITE};
+
+    fn running_init() -> (ProcessTable, i32) {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        (ptable, init)
+    }
+
+    #[test]
+    fn test_relative_paths_follow_cwd() {
+        let (mut ptable, init) = running_init();
+        assert_eq!(ptable.getcwd(init).unwrap(), "/");
+        ptable.mkdir(init, "home").unwrap();
+        ptable.mkdir(init, "/home/ann").unwrap();
+
+        ptable.chdir(init, "home/ann").unwrap();
+        assert_eq!(ptable.getcwd(init).unwrap(), "/home/ann");
+        let fd = ptable.open(init, "notes", O_CREATE | O_WRONLY).unwrap();
+        ptable.write(init, fd, b"hi").unwrap();
+        assert_eq!(
+            ptable.ftable().contents("/home/ann/notes"),
+            Some(&b"hi"[..])
+        );
+
+        // "." and ".." are ordinary entries
+        ptable.chdir(init, "../..").unwrap();
+        assert_eq!(ptable.getcwd(init).unwrap(), "/");
+        assert!(
+            ptable
+                .open(init, "./home/ann/../ann/notes", O_RDONLY)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_fork_duplicates_cwd() {
+        let (mut ptable, init) = running_init();
+        ptable.mkdir(init, "/a").unwrap();
+        ptable.mkdir(init, "/b").unwrap();
+        ptable.chdir(init, "/a").unwrap();
+
+        let child = ptable.fork(init).unwrap();
+        assert_eq!(ptable.getcwd(child).unwrap(), "/a");
+        ptable.yield_cpu(init).unwrap();
+        ptable.run(child).unwrap();
+        ptable.chdir(child, "/b").unwrap();
+        assert_eq!(ptable.getcwd(child).unwrap(), "/b");
+        assert_eq!(ptable.getcwd(init).unwrap(), "/a");
+    }
+
+    #[test]
+    fn test_path_errors() {
+        let (mut ptable, init) = running_init();
+        ptable.open(init, "file", O_CREATE | O_RDWR).unwrap();
+        assert_eq!(ptable.chdir(init, "file"), Err(ProcError::NotADirectory));
+        assert_eq!(ptable.chdir(init, "nope"), Err(ProcError::FileNotFound));
+        assert_eq!(ptable.mkdir(init, "file"), Err(ProcError::FileExists));
+        assert_eq!(
+            ptable.mkdir(init, "file/sub"),
+            Err(ProcError::NotADirectory)
+        );
+        assert_eq!(ptable.mkdir(init, "/"), Err(ProcError::FileExists));
+        assert_eq!(
+            ptable.open(init, "/", O_WRONLY),
+            Err(ProcError::IsADirectory)
+        );
+    }
+
+    #[test]
+    fn test_read_directory_as_dirents() {
+        let (mut ptable, init) = running_init();
+        ptable.mkdir(init, "bin").unwrap();
+        let fd = ptable.open(init, ".", O_RDONLY).unwrap();
+        let dirents = ptable.read(init, fd, 512).unwrap();
+        assert_eq!(dirents.len(), 3 * (2 + DIRSIZ));
+        let names: Vec<_> = dirents
+            .chunks(2 + DIRSIZ)
+            .map(|d| {
+                String::from_utf8_lossy(&d[2..])
+                    .trim_end_matches('\0')
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(names, vec![".", "..", "bin"]);
+    }
+
+    // mkdir d; cd d; echo x > f, run through the system call interface
+    #[test]
+    fn test_shell_script_through_syscalls() {
+        let (mut ptable, init) = running_init();
+        ptable.growproc(init, 4096).unwrap();
+        ptable.trapframe_mut(init).unwrap().esp = 4096;
+        ptable.copyout(init, 0x100, b"d\0f\0x\n").unwrap();
+
+        assert_eq!(ptable.invoke(init, SYS_MKDIR, &[0x100]), Ok(Some(0)));
+        assert_eq!(ptable.invoke(init, SYS_CHDIR, &[0x100]), Ok(Some(0)));
+        let fd = ptable
+            .invoke(init, SYS_OPEN, &[0x102, O_CREATE | O_WRONLY])
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            ptable.invoke(init, SYS_WRITE, &[fd as u32, 0x104, 2]),
+            Ok(Some(2))
+        );
+        assert_eq!(ptable.ftable().contents("/d/f"), Some(&b"x\n"[..]));
+        assert_eq!(
+            ptable.invoke(init, SYS_CHDIR, &[0x102]),
+            Err(ProcError::NotADirectory)
+        );
+    }
+}
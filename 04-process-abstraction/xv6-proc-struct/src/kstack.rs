@@ -0,0 +1,278 @@
+use std::fmt;
+use std::mem::size_of;
+
+use crate::arch::Arch;
+use crate::proc::ProcError;
+use crate::ptable::ProcessTable;
+
+pub const KSTACKSIZE: usize = 4096; // size of per-process kernel stack
+pub const KSTACKGUARD: usize = 64; // bytes at the bottom that must never be written
+pub const KSTACKBASE: u64 = 0xa000_0000; // slot i's stack is the page at KSTACKBASE + i * KSTACKSIZE
+
+// kernel text addresses the new context and stack point into
+pub const FORKRET: u64 = 0x8010_3a00;
+pub const TRAPRET: u64 = 0x8010_6b00;
+
+const CANARY: u8 = 0xa5;
+
+// A simulated kernel stack. allocproc builds the same frame as xv6's:
+// the trap frame at the top, trapret's address below it for forkret to
+// return into, and the context below that. (xv6-riscv keeps the trap
+// frame in its own page and the context in struct proc, so there the
+// stack starts out empty.) Proc::tf and Proc::context stay the working
+// copies of those registers; the stack records where they would be.
+pub struct KStack {
+    base: u64,    // address of the lowest byte
+    mem: Vec<u8>, // mem[0] is at base
+    sp: usize,    // offset of the stack pointer
+    floor: usize, // sp right after allocproc; pops stop here
+    tf: Option<usize>,
+    context: Option<usize>,
+}
+
+impl fmt::Debug for KStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KStack")
+            .field("base", &format_args!("{:#x}", self.base))
+            .field("sp", &format_args!("{:#x}", self.sp()))
+            .field("guard_intact", &self.guard_intact())
+            .finish()
+    }
+}
+
+impl KStack {
+    // xv6's allocproc, for architecture A
+    pub(crate) fn allocproc<A: Arch>(base: u64) -> Self {
+        let mut mem = vec![0; KSTACKSIZE];
+        mem[..KSTACKGUARD].fill(CANARY);
+        let mut stack = KStack {
+            base,
+            mem,
+            sp: KSTACKSIZE,
+            floor: KSTACKSIZE,
+            tf: None,
+            context: None,
+        };
+        if A::TRAPFRAME_ON_KSTACK {
+            stack.sp -= size_of::<A::TrapFrame>();
+            stack.tf = Some(stack.sp);
+
+            // forkret will return to trapret
+            stack.sp -= A::WORD;
+            let sp = stack.sp;
+            stack.mem[sp..sp + A::WORD].copy_from_slice(&TRAPRET.to_le_bytes()[..A::WORD]);
+
+            stack.sp -= size_of::<A::Context>();
+            stack.context = Some(stack.sp);
+        }
+        stack.floor = stack.sp;
+        stack
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    pub fn top(&self) -> u64 {
+        self.base + KSTACKSIZE as u64
+    }
+
+    pub fn sp(&self) -> u64 {
+        self.base + self.sp as u64
+    }
+
+    // where allocproc put the trap frame and context, if on this stack
+    pub fn tf(&self) -> Option<u64> {
+        self.tf.map(|off| self.base + off as u64)
+    }
+
+    pub fn context(&self) -> Option<u64> {
+        self.context.map(|off| self.base + off as u64)
+    }
+
+    // the word at addr, little-endian
+    pub fn read_word(&self, addr: u64, word: usize) -> Option<u64> {
+        let off = addr.checked_sub(self.base)? as usize;
+        let bytes = self.mem.get(off..off + word)?;
+        let mut buf = [0; 8];
+        buf[..word].copy_from_slice(bytes);
+        Some(u64::from_le_bytes(buf))
+    }
+
+    pub fn guard_intact(&self) -> bool {
+        self.mem[..KSTACKGUARD].iter().all(|&b| b == CANARY)
+    }
+
+    // push bytes, refusing to run into the guard
+    fn push(&mut self, bytes: &[u8]) -> Option<u64> {
+        if self.sp < KSTACKGUARD + bytes.len() {
+            return None;
+        }
+        self.sp -= bytes.len();
+        self.mem[self.sp..self.sp + bytes.len()].copy_from_slice(bytes);
+        Some(self.sp())
+    }
+
+    fn pop(&mut self, n: usize) -> Option<Vec<u8>> {
+        if self.sp + n > self.floor {
+            return None;
+        }
+        let bytes = self.mem[self.sp..self.sp + n].to_vec();
+        self.sp += n;
+        Some(bytes)
+    }
+}
+
+impl<A: Arch> ProcessTable<A> {
+    pub fn kstack(&self, pid: i32) -> Option<&KStack> {
+        self.get(pid)?.kstack.as_ref()
+    }
+
+    // push onto a process's kernel stack, as kernel code running for it
+    // would; returns the new stack pointer
+    pub fn kpush(&mut self, pid: i32, bytes: &[u8]) -> Result<u64, ProcError> {
+        self.kstack_mut(pid)?
+            .push(bytes)
+            .ok_or(ProcError::KStackOverflow(pid))
+    }
+
+    pub fn kpop(&mut self, pid: i32, n: usize) -> Result<Vec<u8>, ProcError> {
+        let stack = self.kstack_mut(pid)?;
+        let sp = stack.sp();
+        stack
+            .pop(n)
+            .ok_or(ProcError::BadAddress { pid, va: sp as u32 })
+    }
+
+    // an unchecked store anywhere in the stack page, like a runaway
+    // buffer in kernel code; only the guard check notices
+    pub fn kstack_write(&mut self, pid: i32, addr: u64, bytes: &[u8]) -> Result<(), ProcError> {
+        let stack = self.kstack_mut(pid)?;
+        let bad = ProcError::BadAddress {
+            pid,
+            va: addr as u32,
+        };
+        let off = addr.checked_sub(stack.base).ok_or(bad)? as usize;
+        stack
+            .mem
+            .get_mut(off..off + bytes.len())
+            .ok_or(bad)?
+            .copy_from_slice(bytes);
+        Ok(())
+    }
+
+    pub fn check_kstack(&self, pid: i32) -> Result<(), ProcError> {
+        match self.kstack(pid) {
+            Some(stack) if !stack.guard_intact() => Err(ProcError::KStackOverflow(pid)),
+            _ => Ok(()),
+        }
+    }
+
+    fn kstack_mut(&mut self, pid: i32) -> Result<&mut KStack, ProcError> {
+        self.find_process(pid)
+            .and_then(|p| p.kstack.as_mut())
+            .ok_or(ProcError::NoSuchProcess(pid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{Riscv, X86};
+    use crate::proc::Context;
+    use crate::sched::Scheduler;
+    use crate::trap::TrapFrame;
+
+    #[test]
+    fn test_allocproc_frame_layout() {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+        let stack = ptable.kstack(init).unwrap();
+        assert_eq!(stack.base(), KSTACKBASE);
+
+        // trap frame at the top, then trapret, then the context
+        let tf = stack.tf().unwrap();
+        assert_eq!(tf, stack.top() - size_of::<TrapFrame>() as u64);
+        assert_eq!(stack.read_word(tf - 4, X86::WORD), Some(TRAPRET));
+        let context = stack.context().unwrap();
+        assert_eq!(context, tf - 4 - size_of::<Context>() as u64);
+        assert_eq!(stack.sp(), context);
+
+        // the new context starts in forkret with esp at the context
+        let proc = ptable.get(init).unwrap();
+        assert_eq!(proc.context.eip as u64, FORKRET);
+        assert_eq!(proc.context.esp as u64, context);
+    }
+
+    #[test]
+    fn test_riscv_stack_starts_empty() {
+        let mut ptable = ProcessTable::<Riscv>::for_arch(4, 16);
+        let init = ptable.userinit().unwrap();
+        let stack = ptable.kstack(init).unwrap();
+        assert_eq!((stack.tf(), stack.context()), (None, None));
+        let proc = ptable.get(init).unwrap();
+        assert_eq!((proc.context.ra, proc.context.sp), (FORKRET, stack.top()));
+    }
+
+    #[test]
+    fn test_stacks_are_per_slot_and_freed_on_reap() {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        let child = ptable.fork(init).unwrap();
+        let base = ptable.kstack(child).unwrap().base();
+        assert_eq!(base, KSTACKBASE + KSTACKSIZE as u64);
+
+        ptable.run(child).unwrap();
+        ptable.exit(child, 0).unwrap();
+        ptable.wait(init).unwrap();
+        assert!(ptable.slot(1).kstack.is_none());
+
+        // the slot's next process gets a fresh stack at the same place
+        let next = ptable.fork(init).unwrap();
+        assert_eq!(ptable.kstack(next).unwrap().base(), base);
+    }
+
+    #[test]
+    fn test_push_stops_at_guard() {
+        let mut ptable = ProcessTable::new(2);
+        let init = ptable.userinit().unwrap();
+        let frame = [0u8; 256];
+        let mut pushes = 0;
+        while ptable.kpush(init, &frame).is_ok() {
+            pushes += 1;
+        }
+        // 4096 - 76 - 4 - 32 = 3984 bytes above the 64-byte guard
+        assert_eq!(pushes, (3984 - KSTACKGUARD) / 256);
+        assert_eq!(
+            ptable.kpush(init, &frame),
+            Err(ProcError::KStackOverflow(init))
+        );
+        assert!(ptable.kstack(init).unwrap().guard_intact());
+
+        assert_eq!(ptable.kpop(init, 256).unwrap(), vec![0; 256]);
+        for _ in 1..pushes {
+            ptable.kpop(init, 256).unwrap();
+        }
+        assert!(ptable.kpop(init, 1).is_err());
+    }
+
+    #[test]
+    fn test_scheduler_catches_smashed_guard() {
+        let mut ptable = ProcessTable::new(2);
+        let init = ptable.userinit().unwrap();
+        let mut sched = Scheduler::new();
+        sched.tick(&mut ptable).unwrap();
+
+        let base = ptable.kstack(init).unwrap().base();
+        ptable.kstack_write(init, base + 8, &[0; 4]).unwrap();
+        assert_eq!(
+            ptable.check_kstack(init),
+            Err(ProcError::KStackOverflow(init))
+        );
+        assert_eq!(
+            sched.tick(&mut ptable),
+            Err(ProcError::KStackOverflow(init))
+        );
+    }
+}
@@ -0,0 +1,45 @@
+// xv6 process structures and the process table that manages them
+mod arch;
+mod chan;
+mod exec;
+mod file;
+mod fs;
+mod kstack;
+mod pid;
+mod policy;
+mod proc;
+mod procdump;
+mod ptable;
+mod rng;
+mod sched;
+mod sim;
+mod smp;
+mod stats;
+mod swtch;
+mod syscall;
+mod trap;
+mod vm;
+
+pub use arch::{Arch, Context64, Riscv, RiscvContext, RiscvTrapFrame, TrapFrame64, X86, X86_64};
+pub use chan::Chan;
+pub use exec::{MAXARG, Program};
+pub use file::{File, FileTable, NFILE, O_CREATE, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY};
+pub use fs::{DIRSIZ, FileSystem, Inode, ROOTINO};
+pub use kstack::{FORKRET, KSTACKBASE, KSTACKGUARD, KSTACKSIZE, KStack, TRAPRET};
+pub use pid::{PID_MAX, PidAllocator, PidPolicy};
+pub use policy::{DEFAULT_TICKETS, Lottery, Priority, RoundRobin, STRIDE1, SchedPolicy, Stride};
+pub use proc::{Context, FileHandle, InodeHandle, NOFILE, Proc, ProcError, ProcState};
+pub use procdump::{ProcSnapshot, TableSnapshot};
+pub use ptable::{NPROC, ProcessTable};
+pub use rng::Rng;
+pub use sched::{SchedEvent, Scheduler, Swtch};
+pub use sim::{Sim, SimEvent, Step};
+pub use smp::LockedProcessTable;
+pub use stats::{NREAPED, PInfo, ProcStats, TableStats};
+pub use swtch::{CLOBBERED, Registers};
+pub use syscall::{
+    SYS_CHDIR, SYS_CLOSE, SYS_DUP, SYS_EXEC, SYS_EXIT, SYS_FORK, SYS_GETPID, SYS_KILL, SYS_MKDIR,
+    SYS_OPEN, SYS_READ, SYS_SBRK, SYS_SLEEP, SYS_UPTIME, SYS_WAIT, SYS_WRITE,
+};
+pub use trap::{T_SYSCALL, TrapFrame};
+pub use vm::{PGSIZE, PHYSPAGES, PhysMem, pgroundup};
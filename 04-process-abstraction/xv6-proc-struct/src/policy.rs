@@ -0,0 +1,262 @@
+use crate::proc::{Proc, ProcError, ProcState};
+use crate::ptable::ProcessTable;
+use crate::rng::Rng;
+
+pub const DEFAULT_TICKETS: u32 = 1; // what userinit gets; fork copies the parent's
+pub const STRIDE1: u64 = 1 << 20; // stride of a process holding one ticket
+
+// Decides which runnable process gets the cpu next. The scheduler calls
+// pick_next once per tick (again if the pick turns out to be killed) and
+// reports what happened through the hooks, which default to doing nothing.
+pub trait SchedPolicy {
+    // the slot of a runnable process, or None to idle
+    fn pick_next(&mut self, ptable: &ProcessTable) -> Option<usize>;
+
+    // pid ran for the tick that just ended
+    fn on_tick(&mut self, _ptable: &mut ProcessTable, _pid: i32) {}
+
+    // pid became runnable: it was woken, or it is new
+    fn on_wakeup(&mut self, _ptable: &mut ProcessTable, _pid: i32) {}
+
+    // pid exited
+    fn on_exit(&mut self, _ptable: &mut ProcessTable, _pid: i32) {}
+}
+
+// runnable slots in scan order starting at from
+fn runnable_from(ptable: &ProcessTable, from: usize) -> impl Iterator<Item = usize> + '_ {
+    let slots = ptable.slot_count();
+    (0..slots)
+        .map(move |i| (from + i) % slots)
+        .filter(|&i| ptable.slot(i).is_runnable())
+}
+
+// xv6's scheduler: scan the table from where the last scan stopped
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    next_slot: usize,
+}
+
+impl RoundRobin {
+    pub fn new() -> Self {
+        RoundRobin::default()
+    }
+}
+
+impl SchedPolicy for RoundRobin {
+    fn pick_next(&mut self, ptable: &ProcessTable) -> Option<usize> {
+        let i = runnable_from(ptable, self.next_slot).next()?;
+        self.next_slot = (i + 1) % ptable.slot_count();
+        Some(i)
+    }
+}
+
+// strict priority, lower numbers first; equal priorities take turns
+#[derive(Debug, Default)]
+pub struct Priority {
+    next_slot: usize,
+}
+
+impl Priority {
+    pub fn new() -> Self {
+        Priority::default()
+    }
+}
+
+impl SchedPolicy for Priority {
+    fn pick_next(&mut self, ptable: &ProcessTable) -> Option<usize> {
+        // min_by_key keeps the first of equals, so the scan order breaks ties
+        let i = runnable_from(ptable, self.next_slot).min_by_key(|&i| ptable.slot(i).priority)?;
+        self.next_slot = (i + 1) % ptable.slot_count();
+        Some(i)
+    }
+}
+
+// draw a winning ticket among the runnable processes
+#[derive(Debug)]
+pub struct Lottery {
+    rng: Rng,
+}
+
+impl Lottery {
+    pub fn new(seed: u64) -> Self {
+        Lottery {
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl SchedPolicy for Lottery {
+    fn pick_next(&mut self, ptable: &ProcessTable) -> Option<usize> {
+        let total: u64 = runnable_from(ptable, 0)
+            .map(|i| ptable.slot(i).tickets as u64)
+            .sum();
+        if total == 0 {
+            return runnable_from(ptable, 0).next();
+        }
+        let mut winner = self.rng.below(total);
+        runnable_from(ptable, 0).find(|&i| {
+            let tickets = ptable.slot(i).tickets as u64;
+            if winner < tickets {
+                return true;
+            }
+            winner -= tickets;
+            false
+        })
+    }
+}
+
+// Waldspurger's stride scheduling: run the lowest pass, then advance it
+// by STRIDE1 / tickets. A process that wakes up or is new starts at the
+// lowest pass among the others, so sleeping earns it no credit.
+#[derive(Debug, Default)]
+pub struct Stride;
+
+impl Stride {
+    pub fn new() -> Self {
+        Stride
+    }
+}
+
+fn stride(proc: &Proc) -> u64 {
+    STRIDE1 / proc.tickets.max(1) as u64
+}
+
+impl SchedPolicy for Stride {
+    fn pick_next(&mut self, ptable: &ProcessTable) -> Option<usize> {
+        runnable_from(ptable, 0).min_by_key(|&i| ptable.slot(i).pass)
+    }
+
+    fn on_tick(&mut self, ptable: &mut ProcessTable, pid: i32) {
+        if let Some(proc) = ptable.find_process(pid) {
+            proc.pass += stride(proc);
+        }
+    }
+
+    fn on_wakeup(&mut self, ptable: &mut ProcessTable, pid: i32) {
+        let floor = ptable
+            .iter()
+            .filter(|p| p.pid != pid && (p.is_runnable() || p.is_running()))
+            .map(|p| p.pass)
+            .min();
+        if let (Some(floor), Some(proc)) = (floor, ptable.find_process(pid)) {
+            proc.pass = proc.pass.max(floor);
+        }
+    }
+}
+
+impl ProcessTable {
+    // like the lottery lab's settickets(); zero tickets is refused
+    pub fn settickets(&mut self, pid: i32, tickets: u32) -> Result<(), ProcError> {
+        if tickets == 0 {
+            return Err(ProcError::BadTickets(pid));
+        }
+        self.find_process(pid)
+            .ok_or(ProcError::NoSuchProcess(pid))?
+            .tickets = tickets;
+        Ok(())
+    }
+
+    pub fn setpriority(&mut self, pid: i32, priority: u32) -> Result<(), ProcError> {
+        self.find_process(pid)
+            .ok_or(ProcError::NoSuchProcess(pid))?
+            .priority = priority;
+        Ok(())
+    }
+
+    // processes that became runnable since the scheduler last asked
+    pub(crate) fn take_woken(&mut self) -> Vec<i32> {
+        self.processes
+            .iter_mut()
+            .filter(|p| p.woken && p.state == ProcState::Runnable)
+            .map(|p| {
+                p.woken = false;
+                p.pid
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chan::Chan;
+    use crate::sched::Scheduler;
+
+    // init plus children with the given tickets, all runnable
+    fn table_with(tickets: &[u32]) -> (ProcessTable, Vec<i32>) {
+        let mut ptable = ProcessTable::new(tickets.len() + 2);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        let pids: Vec<i32> = tickets
+            .iter()
+            .map(|&t| {
+                let pid = ptable.fork(init).unwrap();
+                ptable.settickets(pid, t).unwrap();
+                pid
+            })
+            .collect();
+        // init waits for its children so only they compete
+        assert_eq!(ptable.wait(init), Ok(None));
+        (ptable, pids)
+    }
+
+    fn runs(sched: &Scheduler, pid: i32) -> usize {
+        sched.trace().iter().filter(|e| e.next == Some(pid)).count()
+    }
+
+    #[test]
+    fn test_stride_shares_are_exact() {
+        let (mut ptable, pids) = table_with(&[3, 2, 1]);
+        let mut sched = Scheduler::new().with_policy(Stride::new());
+        sched.run_for(&mut ptable, 600).unwrap();
+        let shares: Vec<_> = pids.iter().map(|&p| runs(&sched, p)).collect();
+        assert_eq!(shares, vec![300, 200, 100]);
+    }
+
+    #[test]
+    fn test_lottery_shares_are_close() {
+        let (mut ptable, pids) = table_with(&[30, 10]);
+        let mut sched = Scheduler::new().with_policy(Lottery::new(7));
+        sched.run_for(&mut ptable, 4000).unwrap();
+        let big = runs(&sched, pids[0]) as f64 / 4000.0;
+        assert!((big - 0.75).abs() < 0.03, "share {big}");
+    }
+
+    #[test]
+    fn test_priority_starves_lower_levels() {
+        let (mut ptable, pids) = table_with(&[1, 1, 1]);
+        ptable.setpriority(pids[0], 5).unwrap();
+        let mut sched = Scheduler::new().with_policy(Priority::new());
+        sched.run_for(&mut ptable, 10).unwrap();
+        assert_eq!(runs(&sched, pids[0]), 0);
+        assert_eq!(runs(&sched, pids[1]), 5);
+        assert_eq!(runs(&sched, pids[2]), 5);
+    }
+
+    #[test]
+    fn test_stride_wakeup_earns_no_credit() {
+        let (mut ptable, pids) = table_with(&[1, 1]);
+        let mut sched = Scheduler::new().with_policy(Stride::new());
+        sched.tick(&mut ptable).unwrap();
+        ptable.sleep(pids[0], Chan::Disk(1)).unwrap();
+        sched.run_for(&mut ptable, 50).unwrap();
+        ptable.wakeup(Chan::Disk(1));
+
+        // without the catch-up the sleeper would hold the cpu for ~50 ticks
+        sched.run_for(&mut ptable, 10).unwrap();
+        let last: Vec<_> = sched.trace()[51..].iter().map(|e| e.next).collect();
+        assert_eq!(last.iter().filter(|&&n| n == Some(pids[0])).count(), 5);
+    }
+
+    #[test]
+    fn test_inherited_and_bad_tickets() {
+        let (mut ptable, pids) = table_with(&[4]);
+        ptable.run(pids[0]).unwrap();
+        let child = ptable.fork(pids[0]).unwrap();
+        assert_eq!(ptable.get(child).unwrap().tickets(), 4);
+        assert_eq!(
+            ptable.settickets(child, 0),
+            Err(ProcError::BadTickets(child))
+        );
+    }
+}
@@ -0,0 +1,314 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::arch::{Arch, X86};
+use crate::chan::Chan;
+use crate::kstack::KStack;
+use crate::policy::DEFAULT_TICKETS;
+use crate::stats::ProcStats;
+
+// The registers xv6 will save and restore
+// to stop and subsequently restart a process
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct Context {
+    pub eip: u32, //instruction pointer
+    pub esp: u32, //stack pointer
+    pub ebx: u32, // general purpose registers
+    pub ecx: u32,
+    pub edx: u32,
+    pub esi: u32,
+    pub edi: u32,
+    pub ebp: u32, //base pointer
+}
+
+// The different states a process can be in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ProcState {
+    #[default]
+    Unused,
+    Embryo,
+    Sleeping,
+    Runnable,
+    Running,
+    Zombie,
+}
+
+impl ProcState {
+    // the moves xv6 makes over a process lifetime:
+    // Unused -> Embryo -> Runnable <-> Running -> Sleeping/Zombie -> Unused
+    pub fn can_transition_to(self, next: ProcState) -> bool {
+        use ProcState::*;
+        matches!(
+            (self, next),
+            (Unused, Embryo)          // allocproc
+                | (Embryo, Runnable)  // fork / userinit done
+                | (Embryo, Unused)    // fork failed, slot given back
+                | (Runnable, Running) // scheduler picks it
+                | (Running, Runnable) // yield
+                | (Running, Sleeping) // sleep
+                | (Sleeping, Runnable) // wakeup
+                | (Running, Zombie)   // exit
+                | (Zombie, Unused) // parent reaped it in wait
+        )
+    }
+}
+
+// Errors returned when an operation would break the process lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcError {
+    NoSuchProcess(i32),
+    InvalidTransition {
+        pid: i32,
+        from: ProcState,
+        to: ProcState,
+    },
+    NotRunning {
+        pid: i32,
+        state: ProcState,
+    },
+    ProcTableFull(usize), // the NPROC limit
+    NoFreePid,
+    KStackOverflow(i32),
+    NotADirectory,
+    IsADirectory,
+    FileExists,
+    NoChildren(i32),
+    InitExiting,
+    KillInit,
+    BadFd {
+        pid: i32,
+        fd: usize,
+    },
+    NoFreeFd(i32),
+    FileTableFull,
+    FileNotFound,
+    Killed(i32),
+    OutOfMemory,
+    BadAddress {
+        pid: i32,
+        va: u32,
+    },
+    TooManyArgs,
+    BadTickets(i32),
+    UnknownTrap(u32),
+    UnknownSyscall(u32),
+}
+
+impl fmt::Display for ProcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcError::NoSuchProcess(pid) => write!(f, "no process with pid {}", pid),
+            ProcError::InvalidTransition { pid, from, to } => {
+                write!(f, "pid {}: illegal transition {:?} -> {:?}", pid, from, to)
+            }
+            ProcError::NotRunning { pid, state } => {
+                write!(f, "pid {} is {:?}, not Running", pid, state)
+            }
+            ProcError::ProcTableFull(nproc) => {
+                write!(f, "process table is full ({} processes)", nproc)
+            }
+            ProcError::NoFreePid => write!(f, "no free pid"),
+            ProcError::NotADirectory => write!(f, "not a directory"),
+            ProcError::IsADirectory => write!(f, "is a directory"),
+            ProcError::FileExists => write!(f, "file exists"),
+            ProcError::KStackOverflow(pid) => write!(f, "pid {}: kernel stack overflow", pid),
+            ProcError::NoChildren(pid) => write!(f, "pid {} has no children to wait for", pid),
+            ProcError::InitExiting => write!(f, "init exiting"),
+            ProcError::KillInit => write!(f, "init cannot be killed"),
+            ProcError::BadFd { pid, fd } => write!(f, "pid {}: bad file descriptor {}", pid, fd),
+            ProcError::NoFreeFd(pid) => write!(f, "pid {} has no free file descriptor", pid),
+            ProcError::FileTableFull => write!(f, "open file table is full"),
+            ProcError::FileNotFound => write!(f, "no such file"),
+            ProcError::Killed(pid) => write!(f, "pid {} has been killed", pid),
+            ProcError::OutOfMemory => write!(f, "out of memory"),
+            ProcError::BadAddress { pid, va } => write!(f, "pid {}: bad address {:#x}", pid, va),
+            ProcError::BadTickets(pid) => write!(f, "pid {}: tickets must be positive", pid),
+            ProcError::TooManyArgs => write!(f, "exec arguments do not fit"),
+            ProcError::UnknownTrap(trapno) => write!(f, "unexpected trap {}", trapno),
+            ProcError::UnknownSyscall(num) => write!(f, "unknown sys call {}", num),
+        }
+    }
+}
+
+impl std::error::Error for ProcError {}
+
+// Forward declarations for types that would be defined elsewhere
+pub type FileHandle = usize; // slot in the system-wide file table
+pub type InodeHandle = usize; // i-number in the in-memory filesystem
+
+pub const NOFILE: usize = 16; //maximum open files per process
+
+// the information xv6 tracks about each process
+// including its register context and state
+#[derive(Debug, Default)]
+pub struct Proc<A: Arch = X86> {
+    pub(crate) mem: Vec<usize>, // physical pages backing the address space, in order
+    pub(crate) sz: u32,         // size of process memory
+    pub(crate) kstack: Option<KStack>, // kernel stack, set up by allocate_process
+    pub(crate) state: ProcState, //process state
+    pub(crate) cpu: Option<usize>, // cpu the process is running on
+    pub(crate) pid: i32,        //process ID
+    pub(crate) name: String,    // process name (debugging)
+    pub(crate) parent: Option<i32>, // pid of the parent process
+    pub(crate) chan: Option<Chan>, // If Some, sleeping on chan
+    pub(crate) killed: bool,    // if true, has been killed
+    pub(crate) xstate: i32,     // exit status handed to the parent in wait
+    pub(crate) ofile: [Option<FileHandle>; NOFILE], //open files, indexes into the file table
+    pub(crate) cwd: Option<InodeHandle>, //current directory, the root if None
+    pub(crate) context: A::Context, // switch here to run process
+    pub(crate) tf: A::TrapFrame, //trap frame for current interrupt
+    pub(crate) sleep_until: Option<u64>, // wake tick of an unfinished sleep() syscall
+    pub(crate) stats: ProcStats, // cpu accounting
+    pub(crate) tickets: u32,    // lottery and stride share
+    pub(crate) priority: u32,   // lower runs first under the priority policy
+    pub(crate) pass: u64,       // stride scheduling virtual time
+    pub(crate) woken: bool,     // became runnable since the policy last looked
+}
+
+impl Proc {
+    // constructor for new process
+    pub fn new(pid: i32) -> Self {
+        Proc::embryo(pid)
+    }
+}
+
+impl<A: Arch> Proc<A> {
+    pub(crate) fn embryo(pid: i32) -> Self {
+        Proc {
+            pid,
+            state: ProcState::Embryo,
+            tickets: DEFAULT_TICKETS,
+            ..Default::default()
+        }
+    }
+
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    pub fn state(&self) -> ProcState {
+        self.state
+    }
+
+    pub fn cpu(&self) -> Option<usize> {
+        self.cpu
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn sz(&self) -> u32 {
+        self.sz
+    }
+
+    pub fn parent(&self) -> Option<i32> {
+        self.parent
+    }
+
+    pub fn chan(&self) -> Option<Chan> {
+        self.chan
+    }
+
+    // the open file behind descriptor fd, if any
+    pub fn ofile(&self, fd: usize) -> Option<FileHandle> {
+        self.ofile.get(fd).copied().flatten()
+    }
+
+    pub fn killed(&self) -> bool {
+        self.killed
+    }
+
+    pub fn tickets(&self) -> u32 {
+        self.tickets
+    }
+
+    pub fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    pub fn pass(&self) -> u64 {
+        self.pass
+    }
+
+    // check if process is running
+    pub fn is_running(&self) -> bool {
+        self.state == ProcState::Running
+    }
+
+    // check if process can be scheduled
+    pub fn is_runnable(&self) -> bool {
+        self.state == ProcState::Runnable
+    }
+
+    // Mark process as killed. Only ProcessTable::kill calls this, so it
+    // can refuse init and wake a sleeper.
+    pub(crate) fn kill(&mut self) {
+        self.killed = true;
+    }
+
+    // Set process state, refusing moves xv6 would never make
+    pub fn set_state(&mut self, new_state: ProcState) -> Result<(), ProcError> {
+        if !self.state.can_transition_to(new_state) {
+            return Err(ProcError::InvalidTransition {
+                pid: self.pid,
+                from: self.state,
+                to: new_state,
+            });
+        }
+        self.state = new_state;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_states() {
+        let mut proc = Proc::new(1);
+
+        assert!(!proc.is_running());
+        assert!(!proc.is_runnable());
+
+        proc.set_state(ProcState::Runnable).unwrap();
+        assert!(proc.is_runnable());
+
+        proc.set_state(ProcState::Running).unwrap();
+        assert!(proc.is_running());
+    }
+
+    #[test]
+    fn test_illegal_transition_rejected() {
+        let mut proc = Proc::new(1);
+        proc.set_state(ProcState::Runnable).unwrap();
+        proc.set_state(ProcState::Running).unwrap();
+        proc.set_state(ProcState::Zombie).unwrap();
+
+        // a zombie can only be reaped, never run again
+        let err = proc.set_state(ProcState::Running).unwrap_err();
+        assert_eq!(
+            err,
+            ProcError::InvalidTransition {
+                pid: 1,
+                from: ProcState::Zombie,
+                to: ProcState::Running,
+            }
+        );
+        assert_eq!(proc.state(), ProcState::Zombie);
+    }
+
+    #[test]
+    fn test_transition_table() {
+        use ProcState::*;
+        assert!(Unused.can_transition_to(Embryo));
+        assert!(Sleeping.can_transition_to(Runnable));
+        assert!(!Unused.can_transition_to(Running));
+        assert!(!Sleeping.can_transition_to(Running));
+        assert!(!Runnable.can_transition_to(Zombie));
+        assert!(!Zombie.can_transition_to(Runnable));
+    }
+}
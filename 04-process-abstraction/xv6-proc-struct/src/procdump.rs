@@ -0,0 +1,167 @@
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::arch::Arch;
+use crate::chan::Chan;
+use crate::proc::{Proc, ProcState};
+use crate::ptable::ProcessTable;
+
+// one live process as procdump sees it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcSnapshot {
+    pub pid: i32,
+    pub state: ProcState,
+    pub name: String,
+    pub sz: u32,
+    pub parent: Option<i32>,
+    pub chan: Option<Chan>,
+    pub killed: bool,
+}
+
+impl<A: Arch> From<&Proc<A>> for ProcSnapshot {
+    fn from(p: &Proc<A>) -> Self {
+        ProcSnapshot {
+            pid: p.pid,
+            state: p.state,
+            name: p.name.clone(),
+            sz: p.sz,
+            parent: p.parent,
+            chan: p.chan,
+            killed: p.killed,
+        }
+    }
+}
+
+// the whole table at one tick, in slot order; unused slots are left out
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableSnapshot {
+    pub ticks: u64,
+    pub procs: Vec<ProcSnapshot>,
+}
+
+// state names padded like xv6's procdump table
+fn state_name(state: ProcState) -> &'static str {
+    match state {
+        ProcState::Unused => "unused",
+        ProcState::Embryo => "embryo",
+        ProcState::Sleeping => "sleep ",
+        ProcState::Runnable => "runble",
+        ProcState::Running => "run   ",
+        ProcState::Zombie => "zombie",
+    }
+}
+
+impl<A: Arch> ProcessTable<A> {
+    pub fn snapshot(&self) -> TableSnapshot {
+        TableSnapshot {
+            ticks: self.uptime(),
+            procs: self
+                .iter()
+                .filter(|p| p.state != ProcState::Unused)
+                .map(ProcSnapshot::from)
+                .collect(),
+        }
+    }
+
+    // a listing of live processes like xv6's ^P: one line per process
+    // with pid, state and name, followed by size, parent and channel
+    pub fn procdump(&self) -> String {
+        let mut out = String::new();
+        for p in self.snapshot().procs {
+            let parent = p.parent.map_or("-".to_string(), |pid| pid.to_string());
+            let _ = write!(
+                out,
+                "{} {} {} sz={} ppid={}",
+                p.pid,
+                state_name(p.state),
+                p.name,
+                p.sz,
+                parent
+            );
+            if let Some(chan) = p.chan {
+                let _ = write!(out, " chan={:?}", chan);
+            }
+            if p.killed {
+                out.push_str(" killed");
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::PGSIZE;
+
+    fn small_tree() -> (ProcessTable, i32, i32) {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        ptable.growproc(init, PGSIZE as i64).unwrap();
+        let child = ptable.fork(init).unwrap();
+        ptable.wait(init).unwrap();
+        (ptable, init, child)
+    }
+
+    #[test]
+    fn test_procdump_lines() {
+        let (ptable, init, child) = small_tree();
+        assert_eq!(
+            ptable.procdump(),
+            format!(
+                "{init} sleep  initcode sz=4096 ppid=- chan=Proc({init})\n\
+                 {child} runble initcode sz=4096 ppid={init}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_snapshot_skips_unused_and_round_trips() {
+        let (mut ptable, init, child) = small_tree();
+        ptable.run(child).unwrap();
+        ptable.exit(child, 0).unwrap();
+        ptable.run(init).unwrap();
+        ptable.wait(init).unwrap();
+
+        let snap = ptable.snapshot();
+        assert_eq!(snap.procs.len(), 1);
+        assert_eq!(snap.procs[0].state, ProcState::Running);
+
+        let json = serde_json::to_string(&snap).unwrap();
+        let back: TableSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, snap);
+    }
+
+    #[test]
+    fn test_snapshots_diff_across_ticks() {
+        let (mut ptable, _init, child) = small_tree();
+        let before = ptable.snapshot();
+        ptable.clock();
+        ptable.kill(child).unwrap();
+        let after = ptable.snapshot();
+
+        let changed: Vec<_> = before
+            .procs
+            .iter()
+            .zip(&after.procs)
+            .filter(|(a, b)| a != b)
+            .map(|(a, _)| a.pid)
+            .collect();
+        assert_eq!(changed, vec![child]);
+        assert_eq!(after.ticks, before.ticks + 1);
+    }
+
+    #[test]
+    fn test_fixture_from_json() {
+        let (ptable, init, _child) = small_tree();
+        let fixture = format!(
+            r#"{{"pid":{init},"state":"Sleeping","name":"initcode","sz":4096,
+                "parent":null,"chan":{{"Proc":{init}}},"killed":false}}"#
+        );
+        let expected: ProcSnapshot = serde_json::from_str(&fixture).unwrap();
+        assert_eq!(ptable.snapshot().procs[0], expected);
+    }
+}
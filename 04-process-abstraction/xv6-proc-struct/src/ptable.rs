@@ -0,0 +1,575 @@
+use std::collections::BTreeMap;
+
+use crate::arch::{Arch, X86};
+use crate::chan::Chan;
+use crate::exec::Program;
+use crate::file::FileTable;
+use crate::fs::ROOTINO;
+use crate::kstack::{FORKRET, KSTACKBASE, KSTACKSIZE, KStack};
+use crate::pid::{PidAllocator, PidPolicy};
+use crate::proc::{Proc, ProcError, ProcState};
+use crate::stats::{PInfo, Reaped};
+use crate::vm::{PHYSPAGES, PhysMem};
+
+pub const NPROC: usize = 64; // default process limit, as in xv6's param.h
+
+// safe wrapper for process table operations
+pub struct ProcessTable<A: Arch = X86> {
+    pub(crate) processes: Vec<Proc<A>>,
+    pids: PidAllocator,
+    nproc: usize,          // most slots the table may hold
+    growable: bool,        // if set, nproc is only the initial reservation
+    initproc: Option<i32>, // orphans are handed to this process
+    pub(crate) ftable: FileTable,
+    pub(crate) kmem: PhysMem,
+    ticks: u64,                                     // timer interrupts so far
+    pub(crate) programs: BTreeMap<String, Program>, // what exec can load, by path
+    pub(crate) reaped: Reaped, // final accounting of processes already waited for
+}
+
+impl ProcessTable {
+    pub fn new(max_processes: usize) -> Self {
+        ProcessTable::with_memory(max_processes, PHYSPAGES)
+    }
+
+    // a table backed by npages of simulated physical memory
+    pub fn with_memory(max_processes: usize, npages: usize) -> Self {
+        ProcessTable::for_arch(max_processes, npages)
+    }
+}
+
+impl<A: Arch> ProcessTable<A> {
+    // the same, for another architecture's process layout
+    pub fn for_arch(max_processes: usize, npages: usize) -> Self {
+        ProcessTable {
+            processes: Vec::with_capacity(max_processes),
+            nproc: max_processes,
+            growable: false,
+            pids: PidAllocator::new(PidPolicy::Monotonic),
+            initproc: None,
+            ftable: FileTable::new(),
+            kmem: PhysMem::new(npages),
+            ticks: 0,
+            programs: BTreeMap::new(),
+            reaped: Reaped::default(),
+        }
+    }
+
+    // choose how pids are handed out from now on
+    pub fn set_pid_policy(&mut self, pids: PidAllocator) {
+        self.pids = pids;
+    }
+
+    // let the table grow past its NPROC limit instead of failing
+    pub fn set_growable(&mut self, growable: bool) {
+        self.growable = growable;
+    }
+
+    pub fn nproc(&self) -> usize {
+        self.nproc
+    }
+
+    pub fn allocate_process(&mut self) -> Result<&mut Proc<A>, ProcError> {
+        // First, look for an unused process slot by finding its index
+        let unused_index = self
+            .processes
+            .iter()
+            .position(|p| p.state == ProcState::Unused);
+        // Vec capacity may exceed what was asked for, so count slots
+        if unused_index.is_none() && !self.growable && self.processes.len() >= self.nproc {
+            return Err(ProcError::ProcTableFull(self.nproc));
+        }
+
+        // live and zombie processes keep their pids
+        let mut in_use: Vec<i32> = self
+            .processes
+            .iter()
+            .filter(|p| p.state != ProcState::Unused)
+            .map(|p| p.pid)
+            .collect();
+        in_use.sort_unstable();
+        let pid = self.pids.alloc(&in_use).ok_or(ProcError::NoFreePid)?;
+
+        let mut proc = Proc::embryo(pid);
+        proc.stats.ctime = self.ticks;
+        let slot = unused_index.unwrap_or(self.processes.len());
+        let kstack = KStack::allocproc::<A>(KSTACKBASE + (slot * KSTACKSIZE) as u64);
+        A::init_context(&mut proc.context, FORKRET, kstack.sp());
+        proc.kstack = Some(kstack);
+        if let Some(index) = unused_index {
+            // Reuse existing unused slot
+            self.processes[index] = proc;
+            return Ok(&mut self.processes[index]);
+        }
+
+        // No unused slot found, add a new process
+        self.processes.push(proc);
+        // Return the last element (the one we just pushed)
+        Ok(self.processes.last_mut().unwrap())
+    }
+
+    pub fn find_process(&mut self, pid: i32) -> Option<&mut Proc<A>> {
+        self.processes
+            .iter_mut()
+            .find(|p| p.state != ProcState::Unused && p.pid == pid)
+    }
+
+    pub fn get(&self, pid: i32) -> Option<&Proc<A>> {
+        self.processes
+            .iter()
+            .find(|p| p.state != ProcState::Unused && p.pid == pid)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Proc<A>> {
+        self.processes.iter()
+    }
+
+    // slot-level access for the scheduler, which walks the table in order
+    pub(crate) fn slot_count(&self) -> usize {
+        self.processes.len()
+    }
+
+    pub(crate) fn slot(&self, index: usize) -> &Proc<A> {
+        &self.processes[index]
+    }
+
+    pub(crate) fn slot_mut(&mut self, index: usize) -> &mut Proc<A> {
+        &mut self.processes[index]
+    }
+
+    fn proc_mut(&mut self, pid: i32) -> Result<&mut Proc<A>, ProcError> {
+        self.find_process(pid).ok_or(ProcError::NoSuchProcess(pid))
+    }
+
+    fn transition(&mut self, pid: i32, to: ProcState) -> Result<(), ProcError> {
+        self.proc_mut(pid)?.set_state(to)
+    }
+
+    // set up the first user process, like xv6's userinit
+    pub fn userinit(&mut self) -> Result<i32, ProcError> {
+        let proc = self.allocate_process()?;
+        proc.name = "initcode".to_string();
+        proc.cwd = Some(ROOTINO);
+        proc.set_state(ProcState::Runnable)?;
+        proc.woken = true;
+        let pid = proc.pid;
+        self.initproc = Some(pid);
+        Ok(pid)
+    }
+
+    pub fn initproc(&self) -> Option<i32> {
+        self.initproc
+    }
+
+    fn require_running(&mut self, pid: i32) -> Result<(), ProcError> {
+        let proc = self.proc_mut(pid)?;
+        if !proc.is_running() {
+            return Err(ProcError::NotRunning {
+                pid,
+                state: proc.state,
+            });
+        }
+        Ok(())
+    }
+
+    // create a child of a running process; the child starts Runnable
+    pub fn fork(&mut self, parent_pid: i32) -> Result<i32, ProcError> {
+        self.require_running(parent_pid)?;
+        let parent = self.proc_mut(parent_pid)?;
+        let (ofile, sz, tf, pages) = (parent.ofile, parent.sz, parent.tf, parent.mem.clone());
+        let name = parent.name.clone();
+        let cwd = parent.cwd;
+        let (tickets, priority) = (parent.tickets, parent.priority);
+
+        let child = self.allocate_process()?;
+        let pid = child.pid;
+        let mem = match self.kmem.copyuvm(&pages) {
+            Ok(mem) => mem,
+            Err(e) => {
+                // hand the embryo's slot back
+                let embryo = self.proc_mut(pid)?;
+                embryo.set_state(ProcState::Unused)?;
+                *embryo = Proc::default();
+                return Err(e);
+            }
+        };
+
+        let child = self.proc_mut(pid)?;
+        child.parent = Some(parent_pid);
+        child.name = name;
+        child.cwd = cwd;
+        child.ofile = ofile;
+        child.mem = mem;
+        child.sz = sz;
+        // same user registers, but fork returns 0 in the child
+        child.tf = tf;
+        A::set_return(&mut child.tf, 0);
+        child.tickets = tickets;
+        child.priority = priority;
+        child.set_state(ProcState::Runnable)?;
+        child.woken = true;
+        self.dup_ofiles(&ofile);
+        Ok(pid)
+    }
+
+    // a timer interrupt: advance the clock and wake sleep() callers
+    pub fn clock(&mut self) {
+        self.account_tick();
+        self.ticks += 1;
+        self.wakeup(Chan::Ticks);
+    }
+
+    pub fn uptime(&self) -> u64 {
+        self.ticks
+    }
+
+    // the scheduler hands the cpu to a runnable process
+    pub fn run(&mut self, pid: i32) -> Result<(), ProcError> {
+        let now = self.ticks;
+        self.proc_mut(pid)?.dispatch(now)
+    }
+
+    // give up the cpu for one scheduling round (xv6's yield)
+    pub fn yield_cpu(&mut self, pid: i32) -> Result<(), ProcError> {
+        self.transition(pid, ProcState::Runnable)
+    }
+
+    // a running process blocks until someone calls wakeup(chan)
+    pub fn sleep(&mut self, pid: i32, chan: Chan) -> Result<(), ProcError> {
+        let proc = self.proc_mut(pid)?;
+        proc.set_state(ProcState::Sleeping)?;
+        proc.chan = Some(chan);
+        Ok(())
+    }
+
+    // wake every process sleeping on chan and return their pids. As in
+    // xv6 nothing is remembered: a wakeup with no sleepers is lost, so
+    // callers must re-check their condition before sleeping.
+    pub fn wakeup(&mut self, chan: Chan) -> Vec<i32> {
+        let mut woken = Vec::new();
+        for proc in self.processes.iter_mut() {
+            if proc.state == ProcState::Sleeping && proc.chan == Some(chan) {
+                proc.state = ProcState::Runnable;
+                proc.chan = None;
+                proc.woken = true;
+                woken.push(proc.pid);
+            }
+        }
+        woken
+    }
+
+    // mark a process killed, like xv6's kill: it is not stopped here,
+    // but a sleeper is woken so it notices on its way back to user space.
+    // init may not be killed, since it could never exit.
+    pub fn kill(&mut self, pid: i32) -> Result<(), ProcError> {
+        if self.initproc == Some(pid) {
+            return Err(ProcError::KillInit);
+        }
+        let proc = self.proc_mut(pid)?;
+        proc.kill();
+        if proc.state == ProcState::Sleeping {
+            proc.set_state(ProcState::Runnable)?;
+            proc.chan = None;
+            proc.woken = true;
+        }
+        Ok(())
+    }
+
+    // a running process exits and stays a zombie until its parent
+    // reaps it; any children it leaves behind are passed to init
+    pub fn exit(&mut self, pid: i32, status: i32) -> Result<(), ProcError> {
+        if self.initproc == Some(pid) {
+            return Err(ProcError::InitExiting);
+        }
+        let now = self.ticks;
+        let proc = self.proc_mut(pid)?;
+        proc.set_state(ProcState::Zombie)?;
+        proc.xstate = status;
+        proc.stats.etime = Some(now);
+        // no clock will charge the tick it exits in, so charge it here
+        proc.stats.rticks += 1;
+        let parent = proc.parent;
+        self.close_ofiles(pid);
+
+        let init = self.initproc;
+        let mut orphaned_zombie = false;
+        for child in self.processes.iter_mut() {
+            if child.state != ProcState::Unused && child.parent == Some(pid) {
+                child.parent = init;
+                orphaned_zombie |= child.state == ProcState::Zombie;
+            }
+        }
+
+        // the parent might be sleeping in wait(); so might init, if it
+        // just inherited a zombie
+        if let Some(parent) = parent {
+            self.wakeup(Chan::Proc(parent));
+        }
+        if let (true, Some(init)) = (orphaned_zombie, init) {
+            self.wakeup(Chan::Proc(init));
+        }
+        Ok(())
+    }
+
+    // reap one zombie child of a running parent, returning its pid and
+    // exit status. If children exist but none has exited, the parent
+    // sleeps on its own pid and Ok(None) is returned; exit() wakes it.
+    pub fn wait(&mut self, parent_pid: i32) -> Result<Option<(i32, i32)>, ProcError> {
+        self.require_running(parent_pid)?;
+        if self.proc_mut(parent_pid)?.killed {
+            return Err(ProcError::Killed(parent_pid));
+        }
+
+        let mut have_kids = false;
+        for proc in self.processes.iter_mut() {
+            if proc.state == ProcState::Unused || proc.parent != Some(parent_pid) {
+                continue;
+            }
+            have_kids = true;
+            if proc.state == ProcState::Zombie {
+                let reaped = (proc.pid, proc.xstate);
+                self.reaped.record(PInfo::from(&*proc));
+                proc.set_state(ProcState::Unused)?;
+                self.kmem.freevm(std::mem::take(&mut proc.mem));
+                *proc = Proc::default();
+                return Ok(Some(reaped));
+            }
+        }
+
+        if have_kids {
+            self.sleep(parent_pid, Chan::Proc(parent_pid))?;
+            Ok(None)
+        } else {
+            Err(ProcError::NoChildren(parent_pid))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_creation() {
+        let mut ptable = ProcessTable::new(10);
+
+        let proc = ptable.allocate_process().unwrap();
+        assert_eq!(proc.pid, 1);
+        assert_eq!(proc.state, ProcState::Embryo);
+        assert!(!proc.killed);
+    }
+
+    #[test]
+    fn test_full_lifecycle() {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+
+        let child = ptable.fork(init).unwrap();
+        assert_eq!(ptable.get(child).unwrap().state, ProcState::Runnable);
+
+        ptable.yield_cpu(init).unwrap();
+        ptable.run(child).unwrap();
+        ptable.sleep(child, Chan::Ticks).unwrap();
+        assert_eq!(ptable.wakeup(Chan::Ticks), vec![child]);
+        ptable.run(child).unwrap();
+        ptable.exit(child, 0).unwrap();
+        assert_eq!(ptable.get(child).unwrap().state, ProcState::Zombie);
+
+        ptable.run(init).unwrap();
+        assert_eq!(ptable.wait(init), Ok(Some((child, 0))));
+        assert!(ptable.get(child).is_none());
+    }
+
+    #[test]
+    fn test_illegal_moves_leave_table_untouched() {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+
+        // only a running process may exit, sleep or fork
+        let child = {
+            ptable.run(init).unwrap();
+            let child = ptable.fork(init).unwrap();
+            ptable.yield_cpu(init).unwrap();
+            child
+        };
+        assert!(matches!(
+            ptable.exit(child, 0),
+            Err(ProcError::InvalidTransition { .. })
+        ));
+        assert!(matches!(
+            ptable.sleep(init, Chan::Ticks),
+            Err(ProcError::InvalidTransition { .. })
+        ));
+        assert_eq!(
+            ptable.fork(init),
+            Err(ProcError::NotRunning {
+                pid: init,
+                state: ProcState::Runnable,
+            })
+        );
+        // a live child is not reaped; the parent blocks instead
+        ptable.run(init).unwrap();
+        assert_eq!(ptable.wait(init), Ok(None));
+        assert_eq!(ptable.get(child).unwrap().state, ProcState::Runnable);
+        assert_eq!(ptable.get(init).unwrap().chan(), Some(Chan::Proc(init)));
+
+        assert_eq!(ptable.run(42), Err(ProcError::NoSuchProcess(42)));
+    }
+
+    #[test]
+    fn test_fork_fails_when_table_full() {
+        let mut ptable = ProcessTable::new(1);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        assert_eq!(ptable.fork(init), Err(ProcError::ProcTableFull(1)));
+    }
+
+    #[test]
+    fn test_nproc_is_exact() {
+        // with_capacity(3) may reserve more, but only 3 slots are handed out
+        let mut ptable = ProcessTable::new(3);
+        for _ in 0..3 {
+            ptable.allocate_process().unwrap();
+        }
+        assert_eq!(
+            ptable.allocate_process().map(|p| p.pid),
+            Err(ProcError::ProcTableFull(3))
+        );
+        assert_eq!(ptable.slot_count(), 3);
+    }
+
+    #[test]
+    fn test_growable_table() {
+        let mut ptable = ProcessTable::new(2);
+        ptable.set_growable(true);
+        for pid in 1..=100 {
+            assert_eq!(ptable.allocate_process().unwrap().pid, pid);
+        }
+        assert_eq!(ptable.slot_count(), 100);
+
+        // growth is bounded by the pid space
+        ptable.set_pid_policy(PidAllocator::with_max(PidPolicy::Monotonic, 100));
+        assert_eq!(
+            ptable.allocate_process().map(|p| p.pid),
+            Err(ProcError::NoFreePid)
+        );
+    }
+
+    #[test]
+    fn test_fork_records_parent_pid() {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        let child = ptable.fork(init).unwrap();
+
+        assert_eq!(ptable.get(init).unwrap().parent(), None);
+        assert_eq!(ptable.get(child).unwrap().parent(), Some(init));
+    }
+
+    #[test]
+    fn test_orphans_reparented_to_init_and_reaped() {
+        let mut ptable = ProcessTable::new(8);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        let parent = ptable.fork(init).unwrap();
+        ptable.yield_cpu(init).unwrap();
+
+        ptable.run(parent).unwrap();
+        let orphan = ptable.fork(parent).unwrap();
+        let zombie = ptable.fork(parent).unwrap();
+        ptable.yield_cpu(parent).unwrap();
+        ptable.run(zombie).unwrap();
+        ptable.exit(zombie, 7).unwrap();
+
+        // parent exits without waiting; both children now belong to init
+        ptable.run(parent).unwrap();
+        ptable.exit(parent, 3).unwrap();
+        assert_eq!(ptable.get(orphan).unwrap().parent(), Some(init));
+        assert_eq!(ptable.get(zombie).unwrap().parent(), Some(init));
+
+        ptable.run(init).unwrap();
+        let mut reaped = vec![
+            ptable.wait(init).unwrap().unwrap(),
+            ptable.wait(init).unwrap().unwrap(),
+        ];
+        reaped.sort();
+        assert_eq!(reaped, vec![(parent, 3), (zombie, 7)]);
+
+        // the orphan is still alive, so init blocks until it exits
+        assert_eq!(ptable.wait(init), Ok(None));
+        assert_eq!(ptable.get(init).unwrap().state, ProcState::Sleeping);
+        ptable.run(orphan).unwrap();
+        ptable.exit(orphan, 0).unwrap();
+        assert_eq!(ptable.get(init).unwrap().state, ProcState::Runnable);
+        ptable.run(init).unwrap();
+        assert_eq!(ptable.wait(init), Ok(Some((orphan, 0))));
+        assert_eq!(ptable.wait(init), Err(ProcError::NoChildren(init)));
+    }
+
+    #[test]
+    fn test_wakeup_wakes_every_sleeper_on_chan_only() {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        let a = ptable.fork(init).unwrap();
+        let b = ptable.fork(init).unwrap();
+        ptable.sleep(init, Chan::Pipe(1)).unwrap();
+        ptable.run(a).unwrap();
+        ptable.sleep(a, Chan::Disk(7)).unwrap();
+        ptable.run(b).unwrap();
+        ptable.sleep(b, Chan::Pipe(1)).unwrap();
+
+        assert_eq!(ptable.wakeup(Chan::Pipe(1)), vec![init, b]);
+        assert_eq!(ptable.get(b).unwrap().chan(), None);
+        assert_eq!(ptable.get(a).unwrap().state, ProcState::Sleeping);
+        assert_eq!(ptable.get(a).unwrap().chan(), Some(Chan::Disk(7)));
+    }
+
+    #[test]
+    fn test_wakeup_without_sleepers_is_lost() {
+        let mut ptable = ProcessTable::new(2);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+
+        // the wakeup arrives before the sleep, so it wakes nobody and the
+        // later sleep blocks until the next wakeup
+        assert!(ptable.wakeup(Chan::Disk(3)).is_empty());
+        ptable.sleep(init, Chan::Disk(3)).unwrap();
+        assert_eq!(ptable.get(init).unwrap().state, ProcState::Sleeping);
+        assert_eq!(ptable.wakeup(Chan::Disk(3)), vec![init]);
+    }
+
+    #[test]
+    fn test_kill_unknown_pid() {
+        let mut ptable = ProcessTable::new(2);
+        ptable.userinit().unwrap();
+        assert_eq!(ptable.kill(9), Err(ProcError::NoSuchProcess(9)));
+    }
+
+    #[test]
+    fn test_killed_parent_stops_waiting() {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        let parent = ptable.fork(init).unwrap();
+        ptable.yield_cpu(init).unwrap();
+        ptable.run(parent).unwrap();
+        ptable.fork(parent).unwrap();
+
+        assert_eq!(ptable.wait(parent), Ok(None));
+        ptable.kill(parent).unwrap();
+        assert_eq!(ptable.get(parent).unwrap().chan(), None);
+        ptable.run(parent).unwrap();
+        assert_eq!(ptable.wait(parent), Err(ProcError::Killed(parent)));
+    }
+
+    #[test]
+    fn test_init_cannot_exit() {
+        let mut ptable = ProcessTable::new(2);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        assert_eq!(ptable.exit(init, 0), Err(ProcError::InitExiting));
+        assert!(ptable.get(init).unwrap().is_running());
+    }
+}
@@ -0,0 +1,390 @@
+use crate::policy::{RoundRobin, SchedPolicy};
+use crate::proc::{Context, ProcError, ProcState};
+use crate::ptable::ProcessTable;
+use crate::swtch::Registers;
+
+// called on every context switch, after Registers::swtch has saved
+// into the first context and loaded from the second
+pub type Swtch = Box<dyn FnMut(&mut Context, &Context)>;
+
+// one tick of the cpu loop: who was switched out and who got the cpu
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchedEvent {
+    pub tick: u64,
+    pub prev: Option<i32>, // process switched out at the start of the tick
+    pub next: Option<i32>, // process run for this tick, None if idle
+    pub killed: Vec<i32>,  // killed processes that exited instead of running
+}
+
+// a single cpu running xv6's scheduler() over a process table
+pub struct Scheduler {
+    cpu: usize,       // only cpu 0 advances the clock, as in xv6
+    context: Context, // the scheduler's own saved registers
+    regs: Registers,  // the cpu's live registers
+    swtch: Swtch,
+    current: Option<i32>,
+    policy: Box<dyn SchedPolicy>, // who runs next; round-robin by default
+    ticks: u64,
+    trace: Vec<SchedEvent>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler::new()
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler::with_swtch(Box::new(|_, _| {}))
+    }
+
+    pub fn with_swtch(swtch: Swtch) -> Self {
+        Scheduler::on_cpu(0, swtch)
+    }
+
+    pub fn on_cpu(cpu: usize, swtch: Swtch) -> Self {
+        Scheduler {
+            cpu,
+            context: Context::default(),
+            regs: Registers::default(),
+            swtch,
+            current: None,
+            policy: Box::new(RoundRobin::new()),
+            ticks: 0,
+            trace: Vec::new(),
+        }
+    }
+
+    // e.g. Scheduler::new().with_policy(Stride::new())
+    pub fn with_policy(mut self, policy: impl SchedPolicy + 'static) -> Self {
+        self.policy = Box::new(policy);
+        self
+    }
+
+    pub fn cpu(&self) -> usize {
+        self.cpu
+    }
+
+    pub fn current(&self) -> Option<i32> {
+        self.current
+    }
+
+    // the registers of whatever this cpu is running
+    pub fn regs(&self) -> &Registers {
+        &self.regs
+    }
+
+    pub fn regs_mut(&mut self) -> &mut Registers {
+        &mut self.regs
+    }
+
+    pub fn trace(&self) -> &[SchedEvent] {
+        &self.trace
+    }
+
+    // one timer interrupt: preempt whatever ran last tick, then let the
+    // policy pick the next runnable process. A killed process is never
+    // left running: on its way back to user space the trap path makes it
+    // exit instead. init cannot exit, so a killed flag on it is ignored.
+    pub fn tick(&mut self, ptable: &mut ProcessTable) -> Result<Option<i32>, ProcError> {
+        if self.cpu == 0 {
+            ptable.clock();
+        }
+        let init = ptable.initproc();
+        let mut killed = Vec::new();
+        let prev = self.current.take();
+        // the process may already have slept or exited on its own, and on
+        // a multi-cpu table it may since have been woken and picked up by
+        // another cpu, in which case it is no longer ours to switch out
+        let ours = prev
+            .and_then(|pid| ptable.find_process(pid))
+            .filter(|p| p.cpu == Some(self.cpu));
+        if let Some(proc) = ours {
+            let pid = proc.pid;
+            if proc.state == ProcState::Running {
+                if proc.killed && init != Some(pid) {
+                    ptable.exit(pid, -1)?;
+                    killed.push(pid);
+                } else {
+                    proc.set_state(ProcState::Runnable)?;
+                }
+            }
+            self.switch_out(ptable, pid)?;
+            self.policy.on_tick(ptable, pid);
+            if ptable
+                .get(pid)
+                .is_some_and(|p| p.state == ProcState::Zombie)
+            {
+                self.policy.on_exit(ptable, pid);
+            }
+        }
+        for pid in ptable.take_woken() {
+            self.policy.on_wakeup(ptable, pid);
+        }
+
+        let now = ptable.uptime();
+        let next = loop {
+            let Some(i) = self.policy.pick_next(ptable) else {
+                break None;
+            };
+
+            let proc = ptable.slot_mut(i);
+            proc.dispatch(now)?;
+            proc.cpu = Some(self.cpu);
+            self.regs.swtch(&mut self.context, &proc.context);
+            (self.swtch)(&mut self.context, &proc.context);
+            let pid = proc.pid;
+
+            if proc.killed && init != Some(pid) {
+                ptable.exit(pid, -1)?;
+                self.switch_out(ptable, pid)?;
+                self.policy.on_exit(ptable, pid);
+                killed.push(pid);
+                continue;
+            }
+            self.current = Some(pid);
+            break Some(pid);
+        };
+
+        self.ticks += 1;
+        self.trace.push(SchedEvent {
+            tick: self.ticks,
+            prev,
+            next,
+            killed,
+        });
+        Ok(next)
+    }
+
+    // save the process's registers and resume the scheduler loop; a
+    // smashed kernel stack guard is fatal, like a kernel panic
+    fn switch_out(&mut self, ptable: &mut ProcessTable, pid: i32) -> Result<(), ProcError> {
+        ptable.check_kstack(pid)?;
+        if let Some(proc) = ptable.find_process(pid) {
+            proc.cpu = None;
+            self.regs.swtch(&mut proc.context, &self.context);
+            (self.swtch)(&mut proc.context, &self.context);
+        }
+        Ok(())
+    }
+
+    pub fn run_for(&mut self, ptable: &mut ProcessTable, ticks: u64) -> Result<(), ProcError> {
+        for _ in 0..ticks {
+            self.tick(ptable)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chan::Chan;
+    use crate::swtch::CLOBBERED;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // init plus two children, all runnable, nothing running
+    fn three_runnable() -> (ProcessTable, Vec<i32>) {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        let a = ptable.fork(init).unwrap();
+        let b = ptable.fork(init).unwrap();
+        ptable.yield_cpu(init).unwrap();
+        (ptable, vec![init, a, b])
+    }
+
+    #[test]
+    fn test_round_robin_order() {
+        let (mut ptable, pids) = three_runnable();
+        let mut sched = Scheduler::new();
+
+        let picked: Vec<_> = (0..6)
+            .map(|_| sched.tick(&mut ptable).unwrap().unwrap())
+            .collect();
+        assert_eq!(
+            picked,
+            vec![pids[0], pids[1], pids[2], pids[0], pids[1], pids[2]]
+        );
+
+        // exactly one process is running at any time
+        let running = ptable.iter().filter(|p| p.is_running()).count();
+        assert_eq!(running, 1);
+    }
+
+    #[test]
+    fn test_trace_records_every_tick() {
+        let (mut ptable, pids) = three_runnable();
+        let mut sched = Scheduler::new();
+        sched.run_for(&mut ptable, 2).unwrap();
+
+        // the running process goes to sleep; the next tick skips it
+        ptable.sleep(pids[1], Chan::Disk(0)).unwrap();
+        sched.tick(&mut ptable).unwrap();
+
+        assert_eq!(
+            sched.trace(),
+            &[
+                SchedEvent {
+                    tick: 1,
+                    prev: None,
+                    next: Some(pids[0]),
+                    killed: vec![],
+                },
+                SchedEvent {
+                    tick: 2,
+                    prev: Some(pids[0]),
+                    next: Some(pids[1]),
+                    killed: vec![],
+                },
+                SchedEvent {
+                    tick: 3,
+                    prev: Some(pids[1]),
+                    next: Some(pids[2]),
+                    killed: vec![],
+                },
+            ]
+        );
+        assert_eq!(ptable.get(pids[1]).unwrap().state(), ProcState::Sleeping);
+    }
+
+    #[test]
+    fn test_idle_when_nothing_runnable() {
+        let mut ptable = ProcessTable::new(2);
+        let init = ptable.userinit().unwrap();
+        let mut sched = Scheduler::new();
+
+        sched.tick(&mut ptable).unwrap();
+        ptable.sleep(init, Chan::Disk(0)).unwrap();
+        assert_eq!(sched.tick(&mut ptable), Ok(None));
+        assert_eq!(sched.current(), None);
+        assert_eq!(sched.trace()[1].next, None);
+    }
+
+    #[test]
+    fn test_swtch_hook_sees_both_directions() {
+        let (mut ptable, pids) = three_runnable();
+        for (n, &pid) in pids.iter().enumerate() {
+            ptable.find_process(pid).unwrap().context.eip = 0x1000 * (n as u32 + 1);
+        }
+
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let log = Rc::clone(&seen);
+        let mut sched = Scheduler::with_swtch(Box::new(move |_old, new| {
+            log.borrow_mut().push(new.eip);
+        }));
+        sched.run_for(&mut ptable, 2).unwrap();
+
+        // scheduler -> pid 1, pid 1 -> scheduler, scheduler -> pid 2
+        assert_eq!(*seen.borrow(), vec![0x1000, 0, 0x2000]);
+    }
+
+    #[test]
+    fn test_registers_survive_round_trip() {
+        let (mut ptable, pids) = three_runnable();
+        let mut sched = Scheduler::new();
+
+        // each process sets its registers while it has the cpu
+        for (n, &pid) in pids.iter().enumerate() {
+            assert_eq!(sched.tick(&mut ptable), Ok(Some(pid)));
+            let v = 0x1000 * (n as u32 + 1);
+            *sched.regs_mut() = Registers {
+                eax: v,
+                ebx: v + 1,
+                ecx: v + 2,
+                edx: v + 3,
+                esi: v + 4,
+                edi: v + 5,
+                ebp: v + 6,
+                esp: v + 7,
+                eip: v + 8,
+            };
+        }
+
+        for (n, &pid) in pids.iter().enumerate() {
+            assert_eq!(sched.tick(&mut ptable), Ok(Some(pid)));
+            let v = 0x1000 * (n as u32 + 1);
+            let regs = sched.regs();
+            assert_eq!(
+                (regs.ebx, regs.esi, regs.edi, regs.ebp, regs.esp, regs.eip),
+                (v + 1, v + 4, v + 5, v + 6, v + 7, v + 8)
+            );
+            assert_eq!(
+                (regs.eax, regs.ecx, regs.edx),
+                (CLOBBERED, CLOBBERED, CLOBBERED)
+            );
+        }
+    }
+
+    #[test]
+    fn test_killed_process_exits_instead_of_running() {
+        let (mut ptable, pids) = three_runnable();
+        let mut sched = Scheduler::new();
+        ptable.kill(pids[1]).unwrap();
+
+        // pid 2 would run on tick 2, but exits and pid 3 gets the cpu
+        sched.run_for(&mut ptable, 2).unwrap();
+        assert_eq!(sched.trace()[1].next, Some(pids[2]));
+        assert_eq!(sched.trace()[1].killed, vec![pids[1]]);
+        assert_eq!(ptable.get(pids[1]).unwrap().state(), ProcState::Zombie);
+
+        // on init's next turn it reaps the child with status -1
+        assert_eq!(sched.tick(&mut ptable), Ok(Some(pids[0])));
+        assert_eq!(ptable.wait(pids[0]), Ok(Some((pids[1], -1))));
+    }
+
+    #[test]
+    fn test_init_cannot_be_killed() {
+        let (mut ptable, pids) = three_runnable();
+        let mut sched = Scheduler::new();
+        assert_eq!(sched.tick(&mut ptable), Ok(Some(pids[0])));
+        assert_eq!(ptable.kill(pids[0]), Err(ProcError::KillInit));
+        assert!(!ptable.get(pids[0]).unwrap().killed());
+
+        // scheduling carries on, init included
+        sched.run_for(&mut ptable, 3).unwrap();
+        assert_eq!(sched.trace()[3].next, Some(pids[0]));
+    }
+
+    // a killed flag set on init behind ProcessTable::kill's back must not
+    // wedge the scheduler on init's failing exit
+    #[test]
+    fn test_killed_init_keeps_running() {
+        let (mut ptable, pids) = three_runnable();
+        let mut sched = Scheduler::new();
+        ptable.find_process(pids[0]).unwrap().kill();
+        sched.run_for(&mut ptable, 5).unwrap();
+        assert!(sched.trace().iter().all(|e| e.killed.is_empty()));
+        assert_eq!(sched.trace()[0].next, Some(pids[0]));
+        assert_eq!(sched.trace()[3].next, Some(pids[0]));
+        assert_eq!(ptable.get(pids[0]).unwrap().state(), ProcState::Runnable);
+    }
+
+    #[test]
+    fn test_killed_running_process_exits_at_next_tick() {
+        let (mut ptable, pids) = three_runnable();
+        let mut sched = Scheduler::new();
+        sched.run_for(&mut ptable, 2).unwrap();
+        ptable.kill(pids[1]).unwrap();
+        assert!(ptable.get(pids[1]).unwrap().is_running());
+        sched.tick(&mut ptable).unwrap();
+        assert_eq!(ptable.get(pids[1]).unwrap().state(), ProcState::Zombie);
+        assert_eq!(sched.trace()[2].killed, vec![pids[1]]);
+        assert_eq!(sched.trace()[2].next, Some(pids[2]));
+    }
+
+    #[test]
+    fn test_kill_wakes_sleeper_which_then_exits() {
+        let (mut ptable, pids) = three_runnable();
+        let mut sched = Scheduler::new();
+        sched.run_for(&mut ptable, 2).unwrap();
+        ptable.sleep(pids[1], Chan::Pipe(0)).unwrap();
+
+        ptable.kill(pids[1]).unwrap();
+        assert_eq!(ptable.get(pids[1]).unwrap().state(), ProcState::Runnable);
+        sched.run_for(&mut ptable, 3).unwrap();
+        assert_eq!(ptable.get(pids[1]).unwrap().state(), ProcState::Zombie);
+    }
+}
@@ -0,0 +1,423 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{self, Write};
+
+use crate::chan::Chan;
+use crate::policy::SchedPolicy;
+use crate::proc::{ProcError, ProcState};
+use crate::ptable::{NPROC, ProcessTable};
+use crate::rng::Rng;
+use crate::sched::Scheduler;
+
+// one instruction of a scripted process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Compute(u64),    // use the cpu for n ticks
+    Disk(u64),       // start a disk request and sleep until it completes n ticks later
+    Fork(Vec<Step>), // start a child running its own script
+    Wait,            // reap a child, sleeping until one exits
+    Exit(i32),
+}
+
+// everything the simulation did, in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimEvent {
+    Spawn {
+        tick: u64,
+        parent: i32,
+        pid: i32,
+    },
+    // pid None: the cpu idled
+    Run {
+        tick: u64,
+        pid: Option<i32>,
+    },
+    DiskStart {
+        tick: u64,
+        pid: i32,
+        req: u32,
+        done: u64,
+    },
+    DiskDone {
+        tick: u64,
+        req: u32,
+        woken: Vec<i32>,
+    },
+    Killed {
+        tick: u64,
+        pid: i32,
+    },
+    Exit {
+        tick: u64,
+        pid: i32,
+        status: i32,
+    },
+    Reap {
+        tick: u64,
+        parent: i32,
+        pid: i32,
+        status: i32,
+    },
+}
+
+impl fmt::Display for SimEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimEvent::Spawn { tick, parent, pid } => write!(f, "{tick:>4} fork {parent} -> {pid}"),
+            SimEvent::Run {
+                tick,
+                pid: Some(pid),
+            } => write!(f, "{tick:>4} run {pid}"),
+            SimEvent::Run { tick, pid: None } => write!(f, "{tick:>4} idle"),
+            SimEvent::DiskStart {
+                tick,
+                pid,
+                req,
+                done,
+            } => write!(f, "{tick:>4} disk {req} start by {pid} until {done}"),
+            SimEvent::DiskDone { tick, req, woken } => {
+                write!(f, "{tick:>4} disk {req} done, wake {woken:?}")
+            }
+            SimEvent::Killed { tick, pid } => write!(f, "{tick:>4} killed {pid}"),
+            SimEvent::Exit { tick, pid, status } => {
+                write!(f, "{tick:>4} exit {pid} status {status}")
+            }
+            SimEvent::Reap {
+                tick,
+                parent,
+                pid,
+                status,
+            } => write!(f, "{tick:>4} reap {pid} by {parent} status {status}"),
+        }
+    }
+}
+
+// A single-cpu kernel simulation: the virtual clock is the table's
+// uptime, every step is one timer interrupt, and each process follows
+// its script whenever the scheduler gives it the cpu. init reaps
+// whatever is left. Nothing depends on wall time or hash order, so the
+// same seed and spawns always give the same trace.
+pub struct Sim {
+    ptable: ProcessTable,
+    sched: Scheduler,
+    rng: Rng, // only for random workloads
+    init: i32,
+    started: bool,
+    scripts: BTreeMap<i32, VecDeque<Step>>,
+    disk: BTreeMap<u32, u64>, // outstanding requests and their completion tick
+    next_req: u32,
+    trace: Vec<SimEvent>,
+}
+
+impl Sim {
+    pub fn new(seed: u64) -> Self {
+        let mut ptable = ProcessTable::new(NPROC);
+        let init = ptable.userinit().expect("empty table has room for init");
+        // init stays on the cpu while the workload is set up
+        ptable.run(init).expect("init is runnable");
+        Sim {
+            ptable,
+            sched: Scheduler::new(),
+            rng: Rng::new(seed),
+            init,
+            started: false,
+            scripts: BTreeMap::new(),
+            disk: BTreeMap::new(),
+            next_req: 0,
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: impl SchedPolicy + 'static) -> Self {
+        self.sched = Scheduler::new().with_policy(policy);
+        self
+    }
+
+    pub fn ptable(&self) -> &ProcessTable {
+        &self.ptable
+    }
+
+    pub fn trace(&self) -> &[SimEvent] {
+        &self.trace
+    }
+
+    // the trace one event per line, for golden files
+    pub fn trace_text(&self) -> String {
+        let mut out = String::new();
+        for event in &self.trace {
+            writeln!(out, "{}", event).unwrap();
+        }
+        out
+    }
+
+    // start a child of init running script
+    pub fn spawn(&mut self, script: Vec<Step>) -> Result<i32, ProcError> {
+        self.fork(self.init, script)
+    }
+
+    // a few bursts of compute and disk, then exit
+    pub fn random_workload(&mut self) -> Vec<Step> {
+        let mut script = Vec::new();
+        for _ in 0..1 + self.rng.below(4) {
+            script.push(Step::Compute(1 + self.rng.below(6)));
+            script.push(Step::Disk(1 + self.rng.below(4)));
+        }
+        script.push(Step::Compute(1 + self.rng.below(3)));
+        script.push(Step::Exit(self.rng.below(3) as i32));
+        script
+    }
+
+    pub fn spawn_random(&mut self) -> Result<i32, ProcError> {
+        let script = self.random_workload();
+        self.spawn(script)
+    }
+
+    fn fork(&mut self, parent: i32, script: Vec<Step>) -> Result<i32, ProcError> {
+        let pid = self.ptable.fork(parent)?;
+        self.scripts.insert(pid, script.into());
+        self.trace.push(SimEvent::Spawn {
+            tick: self.ptable.uptime(),
+            parent,
+            pid,
+        });
+        Ok(pid)
+    }
+
+    // every scripted process has exited and been reaped
+    pub fn done(&self) -> bool {
+        self.disk.is_empty()
+            && self
+                .ptable
+                .iter()
+                .all(|p| p.pid() == self.init || p.state() == ProcState::Unused)
+    }
+
+    // one timer interrupt: finish due disk requests, schedule, and run
+    // the picked process for the tick
+    pub fn step(&mut self) -> Result<(), ProcError> {
+        if !self.started {
+            self.ptable.yield_cpu(self.init)?;
+            self.started = true;
+        }
+
+        let now = self.ptable.uptime() + 1;
+        let due: Vec<u32> = self
+            .disk
+            .iter()
+            .filter(|&(_, &done)| done <= now)
+            .map(|(&req, _)| req)
+            .collect();
+        for req in due {
+            self.disk.remove(&req);
+            let woken = self.ptable.wakeup(Chan::Disk(req));
+            self.trace.push(SimEvent::DiskDone {
+                tick: now,
+                req,
+                woken,
+            });
+        }
+
+        let pid = self.sched.tick(&mut self.ptable)?;
+        let killed = self
+            .sched
+            .trace()
+            .last()
+            .map(|e| e.killed.clone())
+            .unwrap_or_default();
+        for pid in killed {
+            self.scripts.remove(&pid);
+            self.trace.push(SimEvent::Killed { tick: now, pid });
+        }
+        self.trace.push(SimEvent::Run { tick: now, pid });
+
+        match pid {
+            Some(pid) if pid == self.init => self.run_init(now),
+            Some(pid) => self.run_script(pid, now),
+            None => Ok(()),
+        }
+    }
+
+    // step until done(), giving up after max_ticks; returns the ticks used
+    pub fn run(&mut self, max_ticks: u64) -> Result<u64, ProcError> {
+        let mut ticks = 0;
+        while !self.done() && ticks < max_ticks {
+            self.step()?;
+            ticks += 1;
+        }
+        Ok(ticks)
+    }
+
+    // init only ever waits for children
+    fn run_init(&mut self, now: u64) -> Result<(), ProcError> {
+        loop {
+            match self.ptable.wait(self.init) {
+                Ok(Some((pid, status))) => self.trace.push(SimEvent::Reap {
+                    tick: now,
+                    parent: self.init,
+                    pid,
+                    status,
+                }),
+                Ok(None) | Err(ProcError::NoChildren(_)) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Follow the script for one tick. A compute step uses up the tick;
+    // instantaneous steps after it (fork, wait, disk, exit) happen at the
+    // end of the same tick, until the process blocks or exits.
+    fn run_script(&mut self, pid: i32, now: u64) -> Result<(), ProcError> {
+        let mut computed = false;
+        loop {
+            let script = self.scripts.get_mut(&pid).expect("scripted process");
+            let Some(step) = script.front_mut() else {
+                // falling off the end of a script is exit(0)
+                return self.exit(pid, 0, now);
+            };
+            match step {
+                Step::Compute(0) => {
+                    script.pop_front();
+                }
+                Step::Compute(n) if !computed => {
+                    *n -= 1;
+                    computed = true;
+                    if *n == 0 {
+                        script.pop_front();
+                    }
+                }
+                Step::Compute(_) => return Ok(()),
+                Step::Disk(n) => {
+                    let done = now + *n;
+                    script.pop_front();
+                    let req = self.next_req;
+                    self.next_req += 1;
+                    self.disk.insert(req, done);
+                    self.trace.push(SimEvent::DiskStart {
+                        tick: now,
+                        pid,
+                        req,
+                        done,
+                    });
+                    return self.ptable.sleep(pid, Chan::Disk(req));
+                }
+                Step::Fork(child) => {
+                    let child = std::mem::take(child);
+                    script.pop_front();
+                    self.fork(pid, child)?;
+                }
+                Step::Wait => match self.ptable.wait(pid) {
+                    Ok(Some((child, status))) => {
+                        self.scripts.get_mut(&pid).unwrap().pop_front();
+                        self.trace.push(SimEvent::Reap {
+                            tick: now,
+                            parent: pid,
+                            pid: child,
+                            status,
+                        });
+                    }
+                    // asleep; wait is retried when it next runs
+                    Ok(None) => return Ok(()),
+                    Err(ProcError::NoChildren(_)) => {
+                        self.scripts.get_mut(&pid).unwrap().pop_front();
+                    }
+                    Err(e) => return Err(e),
+                },
+                Step::Exit(status) => {
+                    let status = *status;
+                    return self.exit(pid, status, now);
+                }
+            }
+        }
+    }
+
+    fn exit(&mut self, pid: i32, status: i32, now: u64) -> Result<(), ProcError> {
+        self.scripts.remove(&pid);
+        self.ptable.exit(pid, status)?;
+        self.trace.push(SimEvent::Exit {
+            tick: now,
+            pid,
+            status,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Lottery;
+
+    #[test]
+    fn test_golden_compute_disk_exit() {
+        let mut sim = Sim::new(0);
+        sim.spawn(vec![Step::Compute(3), Step::Disk(3), Step::Exit(7)])
+            .unwrap();
+        sim.spawn(vec![Step::Compute(2), Step::Exit(0)]).unwrap();
+        sim.run(100).unwrap();
+        assert!(sim.done());
+        assert_eq!(sim.trace_text(), GOLDEN);
+    }
+
+    // a leading "\" line continuation would eat the first line's padding
+    const GOLDEN: &str = "   0 fork 1 -> 2
+   0 fork 1 -> 3
+   1 run 1
+   2 run 2
+   3 run 3
+   4 run 2
+   5 run 3
+   5 exit 3 status 0
+   6 run 1
+   6 reap 3 by 1 status 0
+   7 run 2
+   7 disk 0 start by 2 until 10
+   8 idle
+   9 idle
+  10 disk 0 done, wake [2]
+  10 run 2
+  10 exit 2 status 7
+  11 run 1
+  11 reap 2 by 1 status 7
+";
+
+    #[test]
+    fn test_fork_and_wait_in_scripts() {
+        let mut sim = Sim::new(0);
+        let parent = sim
+            .spawn(vec![
+                Step::Fork(vec![Step::Compute(2), Step::Exit(5)]),
+                Step::Wait,
+                Step::Exit(1),
+            ])
+            .unwrap();
+        sim.run(100).unwrap();
+        assert!(sim.done());
+
+        let reaps: Vec<_> = sim
+            .trace()
+            .iter()
+            .filter_map(|e| match e {
+                SimEvent::Reap {
+                    parent: p, status, ..
+                } => Some((*p, *status)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(reaps, vec![(parent, 5), (1, 1)]);
+    }
+
+    fn random_run(seed: u64) -> String {
+        let mut sim = Sim::new(seed).with_policy(Lottery::new(seed));
+        for _ in 0..6 {
+            sim.spawn_random().unwrap();
+        }
+        sim.run(10_000).unwrap();
+        assert!(sim.done());
+        sim.trace_text()
+    }
+
+    #[test]
+    fn test_same_seed_same_trace() {
+        assert_eq!(random_run(42), random_run(42));
+        assert_ne!(random_run(42), random_run(43));
+    }
+}
@@ -0,0 +1,160 @@
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+
+use crate::proc::ProcError;
+use crate::ptable::ProcessTable;
+use crate::sched::{SchedEvent, Scheduler};
+
+// the process table behind xv6's ptable.lock: every cpu takes the lock
+// before looking at or changing any process
+pub struct LockedProcessTable {
+    lock: Mutex<ProcessTable>,
+}
+
+impl LockedProcessTable {
+    pub fn new(ptable: ProcessTable) -> Self {
+        LockedProcessTable {
+            lock: Mutex::new(ptable),
+        }
+    }
+
+    // acquire(&ptable.lock); a cpu that panicked while holding it has
+    // already failed the run, so the table is still handed out
+    pub fn lock(&self) -> MutexGuard<'_, ProcessTable> {
+        self.lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn into_inner(self) -> ProcessTable {
+        self.lock.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // run the scheduler loop on ncpu OS threads for the given number of
+    // ticks each and return every cpu's trace. Each tick holds the lock
+    // while scheduling and while `work` acts for the process that cpu just
+    // picked, then releases it while that process "runs".
+    pub fn run_cpus<F>(
+        &self,
+        ncpu: usize,
+        ticks: u64,
+        work: F,
+    ) -> Result<Vec<Vec<SchedEvent>>, ProcError>
+    where
+        F: Fn(usize, Option<i32>, &mut ProcessTable) + Sync,
+    {
+        thread::scope(|s| {
+            let cpus: Vec<_> = (0..ncpu)
+                .map(|cpu| {
+                    let work = &work;
+                    s.spawn(move || {
+                        let mut sched = Scheduler::on_cpu(cpu, Box::new(|_, _| {}));
+                        for _ in 0..ticks {
+                            {
+                                let mut ptable = self.lock();
+                                let pid = sched.tick(&mut ptable)?;
+                                work(cpu, pid, &mut ptable);
+                            }
+                            thread::yield_now();
+                        }
+                        Ok(sched.trace().to_vec())
+                    })
+                })
+                .collect();
+
+            cpus.into_iter()
+                .map(|cpu| cpu.join().expect("cpu thread panicked"))
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chan::Chan;
+    use crate::proc::ProcState;
+    use crate::rng::Rng;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    const NCPU: usize = 4;
+
+    fn busy_table(nproc: usize, children: usize) -> ProcessTable {
+        let mut ptable = ProcessTable::new(nproc);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        for _ in 0..children {
+            ptable.fork(init).unwrap();
+        }
+        ptable.yield_cpu(init).unwrap();
+        ptable
+    }
+
+    #[test]
+    fn test_every_cpu_gets_work() {
+        let table = LockedProcessTable::new(busy_table(8, 5));
+        let traces = table.run_cpus(NCPU, 50, |_, _, _| {}).unwrap();
+
+        for trace in &traces {
+            assert_eq!(trace.len(), 50);
+            assert!(trace.iter().all(|e| e.next.is_some()));
+        }
+        // only cpu 0 drives the clock
+        assert_eq!(table.lock().uptime(), 50);
+    }
+
+    #[test]
+    fn test_no_process_runs_on_two_cpus() {
+        let table = LockedProcessTable::new(busy_table(16, 6));
+        let running: Vec<AtomicI32> = (0..NCPU).map(|_| AtomicI32::new(0)).collect();
+        // one generator per cpu; each lock is only ever taken by its own cpu
+        let seed = 0x9e3779b97f4a7c15;
+        let rngs: Vec<Mutex<Rng>> = (0..NCPU)
+            .map(|cpu| Mutex::new(Rng::new(seed + cpu as u64)))
+            .collect();
+
+        table
+            .run_cpus(NCPU, 2000, |cpu, pid, ptable| {
+                running[cpu].store(pid.unwrap_or(0), Ordering::SeqCst);
+                let Some(pid) = pid else { return };
+
+                // the invariant, checked while still holding ptable.lock
+                for (other, slot) in running.iter().enumerate() {
+                    if other != cpu {
+                        assert_ne!(slot.load(Ordering::SeqCst), pid, "pid {} on two cpus", pid);
+                    }
+                }
+                let proc = ptable.get(pid).unwrap();
+                assert_eq!(proc.state(), ProcState::Running);
+                assert_eq!(proc.cpu(), Some(cpu));
+                let on_cpus = ptable.iter().filter(|p| p.is_running()).count();
+                assert!(on_cpus <= NCPU);
+
+                // then act as the running process to keep the table churning
+                let is_init = ptable.initproc() == Some(pid);
+                let mut rng = rngs[cpu].lock().unwrap();
+                match rng.below(6) {
+                    0 => {
+                        let _ = ptable.fork(pid);
+                    }
+                    1 if !is_init => ptable.exit(pid, 0).unwrap(),
+                    2 => {
+                        let _ = ptable.wait(pid);
+                    }
+                    3 if !is_init => ptable.sleep(pid, Chan::Pipe(cpu)).unwrap(),
+                    4 => {
+                        ptable.wakeup(Chan::Pipe(rng.below(NCPU as u64) as usize));
+                    }
+                    _ => {}
+                }
+                if ptable.get(pid).is_some_and(|p| !p.is_running()) {
+                    running[cpu].store(0, Ordering::SeqCst);
+                }
+            })
+            .unwrap();
+
+        let ptable = table.into_inner();
+        for proc in ptable.iter().filter(|p| p.is_running()) {
+            assert!(proc.cpu().is_some());
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::arch::Arch;
+use crate::proc::{Proc, ProcState};
+use crate::ptable::ProcessTable;
+
+// per-process cpu accounting, all in clock ticks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcStats {
+    pub ctime: u64,             // tick the process was allocated
+    pub etime: Option<u64>,     // tick it exited
+    pub first_run: Option<u64>, // tick it was first given a cpu
+    pub rticks: u64,            // ticks spent running
+    pub wticks: u64,            // ticks runnable but waiting for a cpu
+    pub sticks: u64,            // ticks asleep
+    pub scheduled: u64,         // times it was picked to run
+}
+
+impl ProcStats {
+    // creation to exit
+    pub fn turnaround(&self) -> Option<u64> {
+        self.etime.map(|e| e - self.ctime)
+    }
+
+    // creation to first run
+    pub fn response(&self) -> Option<u64> {
+        self.first_run.map(|r| r - self.ctime)
+    }
+}
+
+// one row of getpinfo(), like the pstat table of the xv6 scheduling labs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PInfo {
+    pub pid: i32,
+    pub name: String,
+    pub state: ProcState,
+    pub stats: ProcStats,
+}
+
+impl<A: Arch> From<&Proc<A>> for PInfo {
+    fn from(p: &Proc<A>) -> Self {
+        PInfo {
+            pid: p.pid,
+            name: p.name.clone(),
+            state: p.state,
+            stats: p.stats,
+        }
+    }
+}
+
+pub const NREAPED: usize = 64; // reaped processes getpinfo still lists
+
+// running sums over exited processes, so averages need no history
+#[derive(Debug, Clone, Copy, Default)]
+struct ExitTotals {
+    exited: usize,
+    turnaround: u64,
+    response: u64,
+    wticks: u64,
+    rticks: u64,
+}
+
+impl ExitTotals {
+    fn add(&mut self, s: &ProcStats) {
+        let Some(turnaround) = s.turnaround() else {
+            return;
+        };
+        self.exited += 1;
+        self.turnaround += turnaround;
+        self.response += s.response().unwrap_or(0);
+        self.wticks += s.wticks;
+        self.rticks += s.rticks;
+    }
+}
+
+// What is kept of processes once they are waited for: the rows of the
+// last NREAPED for getpinfo, and totals over all of them for
+// table_stats. Memory stays bounded however long the table runs.
+#[derive(Debug, Default)]
+pub(crate) struct Reaped {
+    recent: VecDeque<PInfo>,
+    totals: ExitTotals,
+}
+
+impl Reaped {
+    pub(crate) fn record(&mut self, info: PInfo) {
+        self.totals.add(&info.stats);
+        if self.recent.len() == NREAPED {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(info);
+    }
+}
+
+// averages over every process that has exited so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TableStats {
+    pub exited: usize,
+    pub avg_turnaround: f64,
+    pub avg_response: f64,
+    pub avg_wait: f64, // runnable-but-waiting ticks
+    pub avg_run: f64,
+}
+
+impl<A: Arch> Proc<A> {
+    pub fn stats(&self) -> &ProcStats {
+        &self.stats
+    }
+
+    // hand the process a cpu, counting the dispatch
+    pub(crate) fn dispatch(&mut self, now: u64) -> Result<(), crate::proc::ProcError> {
+        self.set_state(ProcState::Running)?;
+        self.stats.scheduled += 1;
+        self.stats.first_run.get_or_insert(now);
+        Ok(())
+    }
+}
+
+impl<A: Arch> ProcessTable<A> {
+    // charge the tick that just ended to every live process
+    pub(crate) fn account_tick(&mut self) {
+        for proc in self.processes.iter_mut() {
+            match proc.state {
+                ProcState::Running => proc.stats.rticks += 1,
+                ProcState::Runnable => proc.stats.wticks += 1,
+                ProcState::Sleeping => proc.stats.sticks += 1,
+                _ => {}
+            }
+        }
+    }
+
+    // the accounting of every process still in the table, in slot order,
+    // followed by the last NREAPED processes already reaped, oldest first
+    pub fn getpinfo(&self) -> Vec<PInfo> {
+        self.iter()
+            .filter(|p| p.state != ProcState::Unused)
+            .map(PInfo::from)
+            .chain(self.reaped.recent.iter().cloned())
+            .collect()
+    }
+
+    // every process ever reaped counts, plus zombies not yet waited for
+    pub fn table_stats(&self) -> TableStats {
+        let mut totals = self.reaped.totals;
+        for proc in self.iter() {
+            totals.add(&proc.stats);
+        }
+        if totals.exited == 0 {
+            return TableStats::default();
+        }
+        let n = totals.exited as f64;
+        TableStats {
+            exited: totals.exited,
+            avg_turnaround: totals.turnaround as f64 / n,
+            avg_response: totals.response as f64 / n,
+            avg_wait: totals.wticks as f64 / n,
+            avg_run: totals.rticks as f64 / n,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chan::Chan;
+    use crate::sched::Scheduler;
+
+    #[test]
+    fn test_accounting_under_round_robin() {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+        let mut sched = Scheduler::new();
+        sched.tick(&mut ptable).unwrap(); // tick 1: init runs
+        ptable.fork(init).unwrap();
+        // the table had one slot when tick 1 scanned it, so init runs
+        // again at tick 2; then the child and init alternate
+        sched.run_for(&mut ptable, 4).unwrap();
+
+        let pinfo = ptable.getpinfo();
+        let (i, c) = (pinfo[0].stats, pinfo[1].stats);
+        assert_eq!((i.ctime, i.first_run, i.scheduled), (0, Some(1), 3));
+        assert_eq!((c.ctime, c.first_run, c.scheduled), (1, Some(3), 2));
+        // each clock charges the tick before it; tick 5 is not charged yet
+        assert_eq!((i.rticks, i.wticks), (3, 2));
+        assert_eq!((c.rticks, c.wticks), (1, 3));
+        assert_eq!(c.response(), Some(2));
+    }
+
+    #[test]
+    fn test_exit_and_reap_keep_stats() {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+        let mut sched = Scheduler::new();
+        sched.tick(&mut ptable).unwrap();
+        let child = ptable.fork(init).unwrap();
+        ptable.sleep(init, Chan::Disk(0)).unwrap();
+        sched.run_for(&mut ptable, 3).unwrap(); // child runs ticks 2-4
+        ptable.exit(child, 0).unwrap();
+        ptable.clock(); // tick 5
+        ptable.wakeup(Chan::Disk(0));
+        ptable.run(init).unwrap();
+        assert_eq!(ptable.wait(init), Ok(Some((child, 0))));
+
+        let pinfo = ptable.getpinfo();
+        assert_eq!(pinfo.len(), 2);
+        let reaped = &pinfo[1];
+        assert_eq!(reaped.pid, child);
+        assert_eq!(reaped.state, ProcState::Zombie);
+        assert_eq!(reaped.stats.etime, Some(4));
+        assert_eq!(reaped.stats.turnaround(), Some(3));
+        // ticks 2 and 3 by the clock, tick 4 by exit itself
+        assert_eq!(reaped.stats.rticks, 3);
+        assert_eq!(pinfo[0].stats.sticks, 4);
+
+        let stats = ptable.table_stats();
+        assert_eq!(stats.exited, 1);
+        assert_eq!(stats.avg_turnaround, 3.0);
+        assert_eq!(stats.avg_response, 1.0);
+        assert_eq!(stats.avg_run, 3.0);
+    }
+
+    #[test]
+    fn test_reaped_history_is_bounded() {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        let total = NREAPED + 10;
+        let mut children = Vec::new();
+        for _ in 0..total {
+            let child = ptable.fork(init).unwrap();
+            ptable.yield_cpu(init).unwrap();
+            ptable.run(child).unwrap();
+            ptable.exit(child, 0).unwrap();
+            ptable.run(init).unwrap();
+            assert_eq!(ptable.wait(init), Ok(Some((child, 0))));
+            children.push(child);
+        }
+
+        // init, then only the most recent NREAPED children
+        let pinfo = ptable.getpinfo();
+        assert_eq!(pinfo.len(), 1 + NREAPED);
+        let listed: Vec<i32> = pinfo[1..].iter().map(|p| p.pid).collect();
+        assert_eq!(listed, children[total - NREAPED..]);
+        // but the averages still cover every child
+        assert_eq!(ptable.table_stats().exited, total);
+    }
+}
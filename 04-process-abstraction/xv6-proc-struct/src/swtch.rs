@@ -0,0 +1,101 @@
+use crate::proc::Context;
+
+// what caller-saved registers hold after a switch; code that expects
+// them to survive swtch reads this instead
+pub const CLOBBERED: u32 = 0xdeadbeef;
+
+// a simulated x86 cpu's integer register file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Registers {
+    pub eax: u32,
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32,
+    pub esi: u32,
+    pub edi: u32,
+    pub ebp: u32,
+    pub esp: u32,
+    pub eip: u32,
+}
+
+impl Registers {
+    // xv6's swtch.S: save the callee-saved registers (ebx, esi, edi,
+    // ebp), the stack pointer and the return address into old, then load
+    // them from new. The C calling convention lets swtch trash eax, ecx
+    // and edx, so they come back as CLOBBERED; Context's ecx and edx
+    // slots are neither saved nor loaded.
+    pub fn swtch(&mut self, old: &mut Context, new: &Context) {
+        old.ebx = self.ebx;
+        old.esi = self.esi;
+        old.edi = self.edi;
+        old.ebp = self.ebp;
+        old.esp = self.esp;
+        old.eip = self.eip;
+
+        self.ebx = new.ebx;
+        self.esi = new.esi;
+        self.edi = new.edi;
+        self.ebp = new.ebp;
+        self.esp = new.esp;
+        self.eip = new.eip;
+        self.eax = CLOBBERED;
+        self.ecx = CLOBBERED;
+        self.edx = CLOBBERED;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swtch_saves_callee_saved_only() {
+        let mut regs = Registers {
+            eax: 1,
+            ebx: 2,
+            ecx: 3,
+            edx: 4,
+            esi: 5,
+            edi: 6,
+            ebp: 7,
+            esp: 8,
+            eip: 9,
+        };
+        let mut old = Context::default();
+        let new = Context {
+            eip: 0x100,
+            esp: 0x200,
+            ebx: 0x300,
+            ecx: 0x400,
+            edx: 0x500,
+            esi: 0x600,
+            edi: 0x700,
+            ebp: 0x800,
+        };
+        regs.swtch(&mut old, &new);
+
+        assert_eq!((old.ebx, old.esi, old.edi, old.ebp), (2, 5, 6, 7));
+        assert_eq!((old.esp, old.eip), (8, 9));
+        assert_eq!((old.ecx, old.edx), (0, 0));
+        assert_eq!(
+            regs,
+            Registers {
+                eax: CLOBBERED,
+                ebx: 0x300,
+                ecx: CLOBBERED,
+                edx: CLOBBERED,
+                esi: 0x600,
+                edi: 0x700,
+                ebp: 0x800,
+                esp: 0x200,
+                eip: 0x100,
+            }
+        );
+
+        // and back again
+        let mut scratch = Context::default();
+        regs.swtch(&mut scratch, &old);
+        assert_eq!((regs.ebx, regs.esi, regs.edi, regs.ebp), (2, 5, 6, 7));
+        assert_eq!((regs.esp, regs.eip), (8, 9));
+    }
+}
@@ -0,0 +1,423 @@
+use crate::chan::Chan;
+use crate::exec::MAXARG;
+use crate::proc::ProcError;
+use crate::ptable::ProcessTable;
+use crate::trap::T_SYSCALL;
+
+// system call numbers, as in xv6's syscall.h
+pub const SYS_FORK: u32 = 1;
+pub const SYS_EXIT: u32 = 2;
+pub const SYS_WAIT: u32 = 3;
+pub const SYS_READ: u32 = 5;
+pub const SYS_KILL: u32 = 6;
+pub const SYS_EXEC: u32 = 7;
+pub const SYS_CHDIR: u32 = 9;
+pub const SYS_DUP: u32 = 10;
+pub const SYS_GETPID: u32 = 11;
+pub const SYS_SBRK: u32 = 12;
+pub const SYS_SLEEP: u32 = 13;
+pub const SYS_UPTIME: u32 = 14;
+pub const SYS_OPEN: u32 = 15;
+pub const SYS_WRITE: u32 = 16;
+pub const SYS_MKDIR: u32 = 20;
+pub const SYS_CLOSE: u32 = 21;
+
+// a handler returns the value for eax, or None when the process blocked
+// (or exited) and the call has not returned to user space
+type SyscallFn = fn(&mut ProcessTable, i32) -> Result<Option<i32>, ProcError>;
+
+// indexed by syscall number, like xv6's syscalls[] array
+const SYSCALLS: [Option<SyscallFn>; 22] = {
+    let mut table: [Option<SyscallFn>; 22] = [None; 22];
+    table[SYS_FORK as usize] = Some(sys_fork);
+    table[SYS_EXIT as usize] = Some(sys_exit);
+    table[SYS_WAIT as usize] = Some(sys_wait);
+    table[SYS_READ as usize] = Some(sys_read);
+    table[SYS_KILL as usize] = Some(sys_kill);
+    table[SYS_EXEC as usize] = Some(sys_exec);
+    table[SYS_CHDIR as usize] = Some(sys_chdir);
+    table[SYS_DUP as usize] = Some(sys_dup);
+    table[SYS_GETPID as usize] = Some(sys_getpid);
+    table[SYS_SBRK as usize] = Some(sys_sbrk);
+    table[SYS_SLEEP as usize] = Some(sys_sleep);
+    table[SYS_UPTIME as usize] = Some(sys_uptime);
+    table[SYS_OPEN as usize] = Some(sys_open);
+    table[SYS_WRITE as usize] = Some(sys_write);
+    table[SYS_MKDIR as usize] = Some(sys_mkdir);
+    table[SYS_CLOSE as usize] = Some(sys_close);
+    table
+};
+
+impl ProcessTable {
+    // dispatch on the syscall number in eax and put the result back in
+    // eax; a failed call returns -1 to user space
+    pub fn syscall(&mut self, pid: i32) -> Result<Option<i32>, ProcError> {
+        let num = self
+            .trapframe(pid)
+            .ok_or(ProcError::NoSuchProcess(pid))?
+            .eax;
+        let handler = SYSCALLS
+            .get(num as usize)
+            .copied()
+            .flatten()
+            .ok_or(ProcError::UnknownSyscall(num));
+
+        let ret = handler.and_then(|sys| sys(self, pid));
+        if let Some(tf) = self.trapframe_mut(pid) {
+            match ret {
+                Ok(Some(value)) => tf.eax = value as u32,
+                Ok(None) => {}
+                Err(_) => tf.eax = -1i32 as u32,
+            }
+        }
+        ret
+    }
+
+    // what a user-space syscall stub does: push the arguments and a
+    // return address, load eax and trap into the kernel. A call that
+    // blocked (Ok(None)) is retried by invoking it again once the
+    // process runs, the way the user would re-execute the trap.
+    pub fn invoke(&mut self, pid: i32, num: u32, args: &[u32]) -> Result<Option<i32>, ProcError> {
+        let esp = self
+            .trapframe(pid)
+            .ok_or(ProcError::NoSuchProcess(pid))?
+            .esp;
+        let frame: Vec<u8> = std::iter::once(u32::MAX) // fake return address
+            .chain(args.iter().copied())
+            .flat_map(u32::to_le_bytes)
+            .collect();
+        let sp = esp
+            .checked_sub(frame.len() as u32)
+            .ok_or(ProcError::BadAddress { pid, va: esp })?;
+        self.copyout(pid, sp, &frame)?;
+
+        if let Some(tf) = self.trapframe_mut(pid) {
+            tf.esp = sp;
+            tf.eax = num;
+            tf.trapno = T_SYSCALL;
+        }
+        let ret = self.trap(pid);
+        if let Some(tf) = self.trapframe_mut(pid) {
+            tf.esp = esp;
+        }
+        ret
+    }
+
+    // fetch the nth 32-bit syscall argument from the user stack
+    fn argint(&self, pid: i32, n: u32) -> Result<u32, ProcError> {
+        let esp = self
+            .trapframe(pid)
+            .ok_or(ProcError::NoSuchProcess(pid))?
+            .esp;
+        // skip the return address; a bogus esp near the top of the
+        // address space must fail, not overflow
+        let addr = esp
+            .checked_add(4)
+            .and_then(|a| a.checked_add(4 * n))
+            .ok_or(ProcError::BadAddress { pid, va: esp })?;
+        self.fetchint(pid, addr)
+    }
+
+    // fetch the nth argument as a pointer to a NUL-terminated string
+    fn argstr(&self, pid: i32, n: u32) -> Result<String, ProcError> {
+        let addr = self.argint(pid, n)?;
+        self.fetchstr(pid, addr)
+    }
+
+    fn fetchint(&self, pid: i32, addr: u32) -> Result<u32, ProcError> {
+        let bytes = self.copyin(pid, addr, 4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn fetchstr(&self, pid: i32, addr: u32) -> Result<String, ProcError> {
+        let sz = self.get(pid).ok_or(ProcError::NoSuchProcess(pid))?.sz;
+        let mut s = Vec::new();
+        for va in addr..sz {
+            match self.copyin(pid, va, 1)?[0] {
+                0 => return Ok(String::from_utf8_lossy(&s).into_owned()),
+                b => s.push(b),
+            }
+        }
+        Err(ProcError::BadAddress { pid, va: addr })
+    }
+}
+
+fn sys_fork(ptable: &mut ProcessTable, pid: i32) -> Result<Option<i32>, ProcError> {
+    ptable.fork(pid).map(Some)
+}
+
+fn sys_exit(ptable: &mut ProcessTable, pid: i32) -> Result<Option<i32>, ProcError> {
+    let status = ptable.argint(pid, 0)? as i32;
+    ptable.exit(pid, status)?;
+    Ok(None)
+}
+
+// wait(int *status): the status is copied out when the pointer is non-null
+fn sys_wait(ptable: &mut ProcessTable, pid: i32) -> Result<Option<i32>, ProcError> {
+    let addr = ptable.argint(pid, 0)?;
+    match ptable.wait(pid)? {
+        Some((child, status)) => {
+            if addr != 0 {
+                ptable.copyout(pid, addr, &status.to_le_bytes())?;
+            }
+            Ok(Some(child))
+        }
+        None => Ok(None),
+    }
+}
+
+fn sys_read(ptable: &mut ProcessTable, pid: i32) -> Result<Option<i32>, ProcError> {
+    let fd = ptable.argint(pid, 0)? as usize;
+    let buf = ptable.argint(pid, 1)?;
+    let n = ptable.argint(pid, 2)?;
+    // check the buffer before the read moves the file offset
+    ptable.copyin(pid, buf, n as usize)?;
+    let data = ptable.read(pid, fd, n as usize)?;
+    ptable.copyout(pid, buf, &data)?;
+    Ok(Some(data.len() as i32))
+}
+
+fn sys_kill(ptable: &mut ProcessTable, pid: i32) -> Result<Option<i32>, ProcError> {
+    let target = ptable.argint(pid, 0)? as i32;
+    ptable.kill(target)?;
+    Ok(Some(0))
+}
+
+// exec(path, argv): argv is a user array of string pointers ending in 0
+fn sys_exec(ptable: &mut ProcessTable, pid: i32) -> Result<Option<i32>, ProcError> {
+    let path = ptable.argstr(pid, 0)?;
+    let uargv = ptable.argint(pid, 1)?;
+    let mut argv = Vec::new();
+    loop {
+        if argv.len() > MAXARG {
+            return Err(ProcError::TooManyArgs);
+        }
+        let uarg = ptable.fetchint(pid, uargv + 4 * argv.len() as u32)?;
+        if uarg == 0 {
+            break;
+        }
+        argv.push(ptable.fetchstr(pid, uarg)?);
+    }
+
+    let program = ptable
+        .program(&path)
+        .cloned()
+        .ok_or(ProcError::FileNotFound)?;
+    let argv: Vec<&str> = argv.iter().map(String::as_str).collect();
+    ptable.exec(pid, &program, &argv)?;
+    Ok(Some(0))
+}
+
+fn sys_dup(ptable: &mut ProcessTable, pid: i32) -> Result<Option<i32>, ProcError> {
+    let fd = ptable.argint(pid, 0)? as usize;
+    ptable.dup(pid, fd).map(|fd| Some(fd as i32))
+}
+
+fn sys_getpid(_ptable: &mut ProcessTable, pid: i32) -> Result<Option<i32>, ProcError> {
+    Ok(Some(pid))
+}
+
+fn sys_sbrk(ptable: &mut ProcessTable, pid: i32) -> Result<Option<i32>, ProcError> {
+    let n = ptable.argint(pid, 0)? as i32;
+    ptable.sbrk(pid, n as i64).map(|old| Some(old as i32))
+}
+
+// sleep(n): block until n ticks have passed since the first attempt;
+// every timer tick wakes the sleeper so it can re-check
+fn sys_sleep(ptable: &mut ProcessTable, pid: i32) -> Result<Option<i32>, ProcError> {
+    let n = ptable.argint(pid, 0)? as u64;
+    let now = ptable.uptime();
+    let proc = ptable
+        .find_process(pid)
+        .ok_or(ProcError::NoSuchProcess(pid))?;
+    let until = *proc.sleep_until.get_or_insert(now + n);
+    if now >= until {
+        proc.sleep_until = None;
+        return Ok(Some(0));
+    }
+    ptable.sleep(pid, Chan::Ticks)?;
+    Ok(None)
+}
+
+fn sys_uptime(ptable: &mut ProcessTable, _pid: i32) -> Result<Option<i32>, ProcError> {
+    Ok(Some(ptable.uptime() as i32))
+}
+
+fn sys_open(ptable: &mut ProcessTable, pid: i32) -> Result<Option<i32>, ProcError> {
+    let path = ptable.argstr(pid, 0)?;
+    let flags = ptable.argint(pid, 1)?;
+    ptable.open(pid, &path, flags).map(|fd| Some(fd as i32))
+}
+
+fn sys_chdir(ptable: &mut ProcessTable, pid: i32) -> Result<Option<i32>, ProcError> {
+    let path = ptable.argstr(pid, 0)?;
+    ptable.chdir(pid, &path).map(|_| Some(0))
+}
+
+fn sys_mkdir(ptable: &mut ProcessTable, pid: i32) -> Result<Option<i32>, ProcError> {
+    let path = ptable.argstr(pid, 0)?;
+    ptable.mkdir(pid, &path).map(|_| Some(0))
+}
+
+fn sys_write(ptable: &mut ProcessTable, pid: i32) -> Result<Option<i32>, ProcError> {
+    let fd = ptable.argint(pid, 0)? as usize;
+    let buf = ptable.argint(pid, 1)?;
+    let n = ptable.argint(pid, 2)?;
+    let data = ptable.copyin(pid, buf, n as usize)?;
+    ptable.write(pid, fd, &data).map(|n| Some(n as i32))
+}
+
+fn sys_close(ptable: &mut ProcessTable, pid: i32) -> Result<Option<i32>, ProcError> {
+    let fd = ptable.argint(pid, 0)? as usize;
+    ptable.close(pid, fd)?;
+    Ok(Some(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::{O_CREATE, O_RDWR};
+    use crate::proc::ProcState;
+    use crate::vm::PGSIZE;
+
+    // a running init with one page of memory and its stack at the top
+    fn user_init() -> (ProcessTable, i32) {
+        let mut ptable = ProcessTable::new(4);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        ptable.growproc(init, PGSIZE as i64).unwrap();
+        ptable.trapframe_mut(init).unwrap().esp = PGSIZE as u32;
+        (ptable, init)
+    }
+
+    #[test]
+    fn test_getpid_and_sbrk() {
+        let (mut ptable, init) = user_init();
+        assert_eq!(ptable.invoke(init, SYS_GETPID, &[]), Ok(Some(init)));
+        assert_eq!(
+            ptable.invoke(init, SYS_SBRK, &[100]),
+            Ok(Some(PGSIZE as i32))
+        );
+        assert_eq!(ptable.get(init).unwrap().sz(), PGSIZE as u32 + 100);
+        assert_eq!(ptable.trapframe(init).unwrap().eax, PGSIZE as u32);
+        // the stub pops its arguments again
+        assert_eq!(ptable.trapframe(init).unwrap().esp, PGSIZE as u32);
+    }
+
+    #[test]
+    fn test_argint_rejects_esp_at_top_of_address_space() {
+        let (mut ptable, init) = user_init();
+        let esp = u32::MAX - 2;
+        let tf = ptable.trapframe_mut(init).unwrap();
+        tf.esp = esp;
+        tf.eax = SYS_SBRK;
+        assert_eq!(
+            ptable.syscall(init),
+            Err(ProcError::BadAddress { pid: init, va: esp })
+        );
+        assert_eq!(ptable.trapframe(init).unwrap().eax, -1i32 as u32);
+    }
+
+    #[test]
+    fn test_fork_returns_zero_in_child() {
+        let (mut ptable, init) = user_init();
+        let child = ptable.invoke(init, SYS_FORK, &[]).unwrap().unwrap();
+        assert_eq!(ptable.trapframe(init).unwrap().eax, child as u32);
+        assert_eq!(ptable.trapframe(child).unwrap().eax, 0);
+        // the child resumes inside the same stub frame (just the return address)
+        assert_eq!(ptable.trapframe(child).unwrap().esp, PGSIZE as u32 - 4);
+    }
+
+    #[test]
+    fn test_scripted_exit_and_wait() {
+        let (mut ptable, init) = user_init();
+        let child = ptable.invoke(init, SYS_FORK, &[]).unwrap().unwrap();
+
+        // the parent waits before the child has exited and blocks
+        assert_eq!(ptable.invoke(init, SYS_WAIT, &[0x200]), Ok(None));
+        ptable.run(child).unwrap();
+        assert_eq!(ptable.invoke(child, SYS_EXIT, &[3]), Ok(None));
+        assert_eq!(ptable.get(child).unwrap().state(), ProcState::Zombie);
+
+        // woken by the exit, the parent retries and reaps the child
+        ptable.run(init).unwrap();
+        assert_eq!(ptable.invoke(init, SYS_WAIT, &[0x200]), Ok(Some(child)));
+        assert_eq!(ptable.copyin(init, 0x200, 4).unwrap(), 3i32.to_le_bytes());
+        assert_eq!(
+            ptable.invoke(init, SYS_WAIT, &[0]),
+            Err(ProcError::NoChildren(init))
+        );
+        assert_eq!(ptable.trapframe(init).unwrap().eax, -1i32 as u32);
+    }
+
+    #[test]
+    fn test_open_write_close() {
+        let (mut ptable, init) = user_init();
+        ptable.copyout(init, 0x100, b"console\0").unwrap();
+        ptable.copyout(init, 0x180, b"hi\n").unwrap();
+
+        let fd = ptable
+            .invoke(init, SYS_OPEN, &[0x100, O_CREATE | O_RDWR])
+            .unwrap()
+            .unwrap();
+        assert_eq!(fd, 0);
+        assert_eq!(ptable.invoke(init, SYS_WRITE, &[0, 0x180, 3]), Ok(Some(3)));
+        assert_eq!(ptable.invoke(init, SYS_DUP, &[0]), Ok(Some(1)));
+        assert_eq!(ptable.invoke(init, SYS_CLOSE, &[1]), Ok(Some(0)));
+        assert_eq!(ptable.ftable().contents("console"), Some(&b"hi\n"[..]));
+
+        // read it back through a fresh descriptor into user memory
+        let fd = ptable
+            .invoke(init, SYS_OPEN, &[0x100, O_RDWR])
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            ptable.invoke(init, SYS_READ, &[fd as u32, 0x300, 8]),
+            Ok(Some(3))
+        );
+        assert_eq!(ptable.copyin(init, 0x300, 3).unwrap(), b"hi\n");
+    }
+
+    #[test]
+    fn test_sleep_blocks_for_ticks() {
+        let (mut ptable, init) = user_init();
+        assert_eq!(ptable.invoke(init, SYS_SLEEP, &[2]), Ok(None));
+        assert_eq!(ptable.get(init).unwrap().chan(), Some(Chan::Ticks));
+
+        // each tick wakes the sleeper; it goes back to sleep until 2 have passed
+        ptable.clock();
+        ptable.run(init).unwrap();
+        assert_eq!(ptable.invoke(init, SYS_SLEEP, &[2]), Ok(None));
+        ptable.clock();
+        ptable.run(init).unwrap();
+        assert_eq!(ptable.invoke(init, SYS_SLEEP, &[2]), Ok(Some(0)));
+        assert_eq!(ptable.invoke(init, SYS_UPTIME, &[]), Ok(Some(2)));
+    }
+
+    #[test]
+    fn test_kill_through_syscall() {
+        let (mut ptable, init) = user_init();
+        let child = ptable.invoke(init, SYS_FORK, &[]).unwrap().unwrap();
+        assert_eq!(ptable.invoke(init, SYS_KILL, &[child as u32]), Ok(Some(0)));
+
+        // the child exits as soon as it traps into the kernel
+        ptable.yield_cpu(init).unwrap();
+        ptable.run(child).unwrap();
+        assert_eq!(ptable.invoke(child, SYS_GETPID, &[]), Ok(None));
+        assert_eq!(ptable.get(child).unwrap().state(), ProcState::Zombie);
+    }
+
+    #[test]
+    fn test_unknown_syscall() {
+        let (mut ptable, init) = user_init();
+        assert_eq!(
+            ptable.invoke(init, 99, &[]),
+            Err(ProcError::UnknownSyscall(99))
+        );
+        assert_eq!(
+            ptable.invoke(init, 4, &[]),
+            Err(ProcError::UnknownSyscall(4))
+        );
+        assert_eq!(ptable.trapframe(init).unwrap().eax, u32::MAX);
+    }
+}
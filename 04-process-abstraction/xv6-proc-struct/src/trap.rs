@@ -0,0 +1,80 @@
+use crate::arch::Arch;
+use crate::proc::ProcError;
+use crate::ptable::ProcessTable;
+
+pub const T_SYSCALL: u32 = 64; // system call vector, as in xv6's traps.h
+
+// Layout of the trap frame built on the kernel stack by the hardware
+// and by trapasm.S, laid out like xv6's struct trapframe
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct TrapFrame {
+    // registers as pushed by pusha
+    pub edi: u32,
+    pub esi: u32,
+    pub ebp: u32,
+    pub oesp: u32, // useless & ignored
+    pub ebx: u32,
+    pub edx: u32,
+    pub ecx: u32,
+    pub eax: u32, // syscall number in, return value out
+
+    // rest of trap frame
+    pub gs: u16,
+    pub padding1: u16,
+    pub fs: u16,
+    pub padding2: u16,
+    pub es: u16,
+    pub padding3: u16,
+    pub ds: u16,
+    pub padding4: u16,
+    pub trapno: u32,
+
+    // below here defined by x86 hardware
+    pub err: u32,
+    pub eip: u32,
+    pub cs: u16,
+    pub padding5: u16,
+    pub eflags: u32,
+
+    // below here only when crossing rings, such as from user to kernel
+    pub esp: u32,
+    pub ss: u16,
+    pub padding6: u16,
+}
+
+impl<A: Arch> ProcessTable<A> {
+    pub fn trapframe(&self, pid: i32) -> Option<&A::TrapFrame> {
+        self.get(pid).map(|p| &p.tf)
+    }
+
+    pub fn trapframe_mut(&mut self, pid: i32) -> Option<&mut A::TrapFrame> {
+        self.find_process(pid).map(|p| &mut p.tf)
+    }
+}
+
+impl ProcessTable {
+    // entry from user space (xv6's trap for T_SYSCALL): a killed process
+    // exits on the way in and on the way out (init never does). Returns
+    // the syscall result, or None if the process blocked or exited
+    // instead of returning.
+    pub fn trap(&mut self, pid: i32) -> Result<Option<i32>, ProcError> {
+        let proc = self.get(pid).ok_or(ProcError::NoSuchProcess(pid))?;
+        if proc.tf.trapno != T_SYSCALL {
+            return Err(ProcError::UnknownTrap(proc.tf.trapno));
+        }
+        let init = self.initproc();
+        if proc.killed && init != Some(pid) {
+            self.exit(pid, -1)?;
+            return Ok(None);
+        }
+
+        let ret = self.syscall(pid);
+
+        if init != Some(pid) && self.get(pid).is_some_and(|p| p.killed && p.is_running()) {
+            self.exit(pid, -1)?;
+            return Ok(None);
+        }
+        ret
+    }
+}
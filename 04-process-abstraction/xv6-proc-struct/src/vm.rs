@@ -0,0 +1,286 @@
+use crate::arch::Arch;
+use crate::proc::ProcError;
+use crate::ptable::ProcessTable;
+
+pub const PGSIZE: usize = 4096; // bytes per page
+pub const PHYSPAGES: usize = 1024; // default size of simulated physical memory (4 MiB)
+
+// round a size up to a whole number of pages
+pub fn pgroundup(sz: usize) -> usize {
+    sz.div_ceil(PGSIZE) * PGSIZE
+}
+
+// simulated physical memory: a fixed arena of pages handed out by
+// kalloc and returned by kfree, like xv6's kmem free list
+#[derive(Debug)]
+pub struct PhysMem {
+    data: Vec<u8>,
+    free: Vec<usize>, // free page numbers, lowest popped first
+}
+
+impl PhysMem {
+    pub fn new(npages: usize) -> Self {
+        PhysMem {
+            data: vec![0; npages * PGSIZE],
+            free: (0..npages).rev().collect(),
+        }
+    }
+
+    pub fn total_pages(&self) -> usize {
+        self.data.len() / PGSIZE
+    }
+
+    pub fn free_pages(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn used_pages(&self) -> usize {
+        self.total_pages() - self.free_pages()
+    }
+
+    // a zeroed page, or None when memory is exhausted
+    fn kalloc(&mut self) -> Option<usize> {
+        let pa = self.free.pop()?;
+        self.page_mut(pa).fill(0);
+        Some(pa)
+    }
+
+    fn kfree(&mut self, pa: usize) {
+        self.free.push(pa);
+    }
+
+    fn page(&self, pa: usize) -> &[u8] {
+        &self.data[pa * PGSIZE..(pa + 1) * PGSIZE]
+    }
+
+    fn page_mut(&mut self, pa: usize) -> &mut [u8] {
+        &mut self.data[pa * PGSIZE..(pa + 1) * PGSIZE]
+    }
+
+    // grow a page list until it maps newsz bytes; on failure every page
+    // allocated by this call is given back (allocuvm)
+    pub(crate) fn allocuvm(
+        &mut self,
+        pages: &mut Vec<usize>,
+        newsz: usize,
+    ) -> Result<(), ProcError> {
+        let old = pages.len();
+        while pages.len() * PGSIZE < newsz {
+            match self.kalloc() {
+                Some(pa) => pages.push(pa),
+                None => {
+                    self.deallocuvm(pages, old * PGSIZE);
+                    return Err(ProcError::OutOfMemory);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // shrink a page list to the pages needed for newsz bytes (deallocuvm)
+    pub(crate) fn deallocuvm(&mut self, pages: &mut Vec<usize>, newsz: usize) {
+        let keep = pgroundup(newsz) / PGSIZE;
+        while pages.len() > keep {
+            let pa = pages.pop().unwrap();
+            self.kfree(pa);
+        }
+    }
+
+    // a fresh copy of an address space for fork (copyuvm)
+    pub(crate) fn copyuvm(&mut self, pages: &[usize]) -> Result<Vec<usize>, ProcError> {
+        let mut copy = Vec::with_capacity(pages.len());
+        for &pa in pages {
+            let Some(new) = self.kalloc() else {
+                self.freevm(copy);
+                return Err(ProcError::OutOfMemory);
+            };
+            self.data
+                .copy_within(pa * PGSIZE..(pa + 1) * PGSIZE, new * PGSIZE);
+            copy.push(new);
+        }
+        Ok(copy)
+    }
+
+    // copy bytes into an address space at va; the caller checks bounds
+    pub(crate) fn write(&mut self, pages: &[usize], va: usize, buf: &[u8]) {
+        for (i, byte) in buf.iter().enumerate() {
+            let a = va + i;
+            self.page_mut(pages[a / PGSIZE])[a % PGSIZE] = *byte;
+        }
+    }
+
+    pub(crate) fn freevm(&mut self, pages: Vec<usize>) {
+        for pa in pages {
+            self.kfree(pa);
+        }
+    }
+}
+
+impl<A: Arch> ProcessTable<A> {
+    pub fn kmem(&self) -> &PhysMem {
+        &self.kmem
+    }
+
+    // grow or shrink a process's memory by n bytes (xv6's growproc)
+    pub fn growproc(&mut self, pid: i32, n: i64) -> Result<(), ProcError> {
+        let proc = self
+            .find_process(pid)
+            .ok_or(ProcError::NoSuchProcess(pid))?;
+        let newsz = proc.sz as i64 + n;
+        if newsz < 0 {
+            return Err(ProcError::BadAddress { pid, va: proc.sz });
+        }
+        let newsz = u32::try_from(newsz).map_err(|_| ProcError::OutOfMemory)?;
+
+        let mut pages = std::mem::take(&mut proc.mem);
+        let grown = if n >= 0 {
+            self.kmem.allocuvm(&mut pages, newsz as usize)
+        } else {
+            self.kmem.deallocuvm(&mut pages, newsz as usize);
+            Ok(())
+        };
+
+        let proc = self
+            .find_process(pid)
+            .ok_or(ProcError::NoSuchProcess(pid))?;
+        proc.mem = pages;
+        grown?;
+        proc.sz = newsz;
+        Ok(())
+    }
+
+    // the sbrk system call: grow by n and return the old break
+    pub fn sbrk(&mut self, pid: i32, n: i64) -> Result<u32, ProcError> {
+        let old = self.get(pid).ok_or(ProcError::NoSuchProcess(pid))?.sz;
+        self.growproc(pid, n)?;
+        Ok(old)
+    }
+
+    // pages held by one process
+    pub fn mem_pages(&self, pid: i32) -> Option<usize> {
+        self.get(pid).map(|p| p.mem.len())
+    }
+
+    // copy bytes into a process's address space at va
+    pub fn copyout(&mut self, pid: i32, va: u32, buf: &[u8]) -> Result<(), ProcError> {
+        let proc = self.get(pid).ok_or(ProcError::NoSuchProcess(pid))?;
+        let start = va as usize;
+        if start + buf.len() > proc.sz as usize {
+            return Err(ProcError::BadAddress { pid, va });
+        }
+        let pages = proc.mem.clone();
+        self.kmem.write(&pages, start, buf);
+        Ok(())
+    }
+
+    // copy len bytes out of a process's address space at va
+    pub fn copyin(&self, pid: i32, va: u32, len: usize) -> Result<Vec<u8>, ProcError> {
+        let proc = self.get(pid).ok_or(ProcError::NoSuchProcess(pid))?;
+        let start = va as usize;
+        if start + len > proc.sz as usize {
+            return Err(ProcError::BadAddress { pid, va });
+        }
+        Ok((start..start + len)
+            .map(|a| self.kmem.page(proc.mem[a / PGSIZE])[a % PGSIZE])
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proc::ProcState;
+
+    fn running_init(npages: usize) -> (ProcessTable, i32) {
+        let mut ptable = ProcessTable::with_memory(4, npages);
+        let init = ptable.userinit().unwrap();
+        ptable.run(init).unwrap();
+        (ptable, init)
+    }
+
+    #[test]
+    fn test_sbrk_grows_by_pages() {
+        let (mut ptable, init) = running_init(8);
+        assert_eq!(ptable.sbrk(init, 100), Ok(0));
+        assert_eq!(ptable.sbrk(init, PGSIZE as i64), Ok(100));
+        assert_eq!(ptable.get(init).unwrap().sz(), 100 + PGSIZE as u32);
+        assert_eq!(ptable.mem_pages(init), Some(2));
+        assert_eq!(ptable.kmem().used_pages(), 2);
+
+        // shrinking hands whole pages back
+        ptable.growproc(init, -(PGSIZE as i64)).unwrap();
+        assert_eq!(ptable.mem_pages(init), Some(1));
+        assert_eq!(ptable.kmem().free_pages(), 7);
+        assert!(ptable.growproc(init, -1000).is_err());
+    }
+
+    #[test]
+    fn test_out_of_memory_is_an_error() {
+        let (mut ptable, init) = running_init(2);
+        ptable.growproc(init, PGSIZE as i64).unwrap();
+        assert_eq!(
+            ptable.growproc(init, 2 * PGSIZE as i64),
+            Err(ProcError::OutOfMemory)
+        );
+        // the failed call leaves the process and the arena as they were
+        assert_eq!(ptable.get(init).unwrap().sz(), PGSIZE as u32);
+        assert_eq!(ptable.kmem().free_pages(), 1);
+    }
+
+    #[test]
+    fn test_fork_copies_address_space() {
+        let (mut ptable, init) = running_init(8);
+        ptable.growproc(init, 2 * PGSIZE as i64).unwrap();
+        ptable.copyout(init, 4090, b"hello world").unwrap();
+
+        let child = ptable.fork(init).unwrap();
+        assert_eq!(ptable.get(child).unwrap().sz(), 2 * PGSIZE as u32);
+        assert_eq!(ptable.copyin(child, 4090, 11).unwrap(), b"hello world");
+        assert_eq!(ptable.kmem().used_pages(), 4);
+
+        // the copy is private: parent writes do not show up in the child
+        ptable.copyout(init, 4090, b"HELLO").unwrap();
+        assert_eq!(ptable.copyin(child, 4090, 5).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_fork_without_memory_fails_cleanly() {
+        let (mut ptable, init) = running_init(3);
+        ptable.growproc(init, 2 * PGSIZE as i64).unwrap();
+        assert_eq!(ptable.fork(init), Err(ProcError::OutOfMemory));
+        assert_eq!(
+            ptable
+                .iter()
+                .filter(|p| p.state() != ProcState::Unused)
+                .count(),
+            1
+        );
+        assert_eq!(ptable.kmem().free_pages(), 1);
+    }
+
+    #[test]
+    fn test_reaping_returns_memory() {
+        let (mut ptable, init) = running_init(8);
+        let child = ptable.fork(init).unwrap();
+        ptable.yield_cpu(init).unwrap();
+        ptable.run(child).unwrap();
+        ptable.growproc(child, 3 * PGSIZE as i64).unwrap();
+        ptable.exit(child, 0).unwrap();
+        assert_eq!(ptable.kmem().used_pages(), 3);
+
+        ptable.run(init).unwrap();
+        ptable.wait(init).unwrap();
+        assert_eq!(ptable.kmem().used_pages(), 0);
+    }
+
+    #[test]
+    fn test_copy_bounds_checked() {
+        let (mut ptable, init) = running_init(2);
+        ptable.growproc(init, 10).unwrap();
+        assert_eq!(
+            ptable.copyout(init, 8, b"abc"),
+            Err(ProcError::BadAddress { pid: init, va: 8 })
+        );
+        assert!(ptable.copyin(init, 0, 11).is_err());
+    }
+}
@@ -1,31 +1,62 @@
-use std::cmp::Reverse;
-use std::collections::BinaryHeap;
+use std::collections::{BTreeMap, BinaryHeap};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 struct Job {
     id: u32,
     tickets: u32,
-    pass: u32,
-    stride: u32,
+    pass: u64, // wraps; see the Ord impl
+    stride: u64,
+    remain: i64, // pass left to the next turn while off the queue
+    frac: u64,   // stride * time not yet a whole unit of pass, in 1/quantum units
 }
 
 impl Job {
-    fn new(id: u32, tickets: u32, large_number: u32) -> Self {
-        let stride = if tickets > 0 {
-            large_number / tickets
-        } else {
-            large_number
-        };
+    fn new(id: u32, tickets: u32, large_number: u64) -> Self {
+        let stride = large_number
+            .checked_div(tickets as u64)
+            .unwrap_or(large_number);
 
         Job {
             id,
             tickets,
             pass: 0,
             stride,
+            remain: stride as i64, // a new job waits one full stride
+            frac: 0,
         }
     }
 }
 
+// Advance pass by stride * used / quantum, carrying the remainder in
+// frac so that many short runs add up to exactly what one long run
+// would. A zero quantum charges the full stride.
+fn advance(pass: &mut u64, frac: &mut u64, stride: u64, used: u32, quantum: u32) {
+    if quantum == 0 {
+        *pass = pass.wrapping_add(stride);
+        return;
+    }
+    let total = stride as u128 * used as u128 + *frac as u128;
+    *pass = pass.wrapping_add((total / quantum as u128) as u64);
+    *frac = (total % quantum as u128) as u64;
+}
+
+// How far a is ahead of b, assuming the two are within 2^63 of each
+// other. Passes wrap around u64 instead of overflowing, and this is
+// the only way they are ever compared.
+fn pass_diff(a: u64, b: u64) -> i64 {
+    a.wrapping_sub(b) as i64
+}
+
+// Equality uses the same (pass, id) key as Ord, so a == b exactly
+// when neither would run before the other
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Job {}
+
 // Implement ordering for priority queue (min-heap based on pass value)
 impl PartialOrd for Job {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
@@ -35,15 +66,32 @@ impl PartialOrd for Job {
 
 impl Ord for Job {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        // compare by pass vaue (for min-heap, we want smallest pass first)
-        other.pass.cmp(&self.pass)
+        // compare by pass vaue (for min-heap, we want smallest pass first).
+        // Queued passes all sit within a few strides of the global pass,
+        // far less than 2^63 apart, so the wrapping difference orders
+        // them correctly even after pass has wrapped past u64::MAX.
+        //
+        // Equal passes go to the lower id. BinaryHeap says nothing about
+        // the order of equal elements, so without this a schedule could
+        // change with the std version. Ids are assumed unique.
+        pass_diff(other.pass, self.pass)
+            .cmp(&0)
+            .then_with(|| other.id.cmp(&self.id))
     }
 }
 
 struct StrideScheduler {
     queue: BinaryHeap<Job>,
-    large_number: u32,
+    large_number: u64,
     quantum: u32,
+    global_tickets: u64, // tickets held by every job in the queue
+    global_pass: u64,    // virtual time, advanced by the global stride each quantum
+    global_frac: u64,
+    // Subgroups, keyed by the id of the job entry that stands for them in
+    // the queue. Each group is a scheduler of its own whose tickets are
+    // its currency; see exchange_rate().
+    groups: BTreeMap<u32, StrideScheduler>,
+    idle: Vec<Job>, // entries for groups with nothing to run, off the queue
 }
 
 impl StrideScheduler {
@@ -52,56 +100,296 @@ impl StrideScheduler {
             queue: BinaryHeap::new(),
             large_number: 10000, // large constant for stride calculation
             quantum,
+            global_tickets: 0,
+            global_pass: 0,
+            global_frac: 0,
+            groups: BTreeMap::new(),
+            idle: Vec::new(),
         }
     }
 
+    // Strides are large_number / tickets, so a bigger large_number gives
+    // finer shares for jobs with many tickets. Set it before adding jobs.
+    // Strides must stay below 2^63 for passes to compare correctly.
+    fn with_large_number(mut self, large_number: u64) -> Self {
+        assert!(large_number <= i64::MAX as u64, "large_number too big");
+        self.large_number = large_number;
+        self
+    }
+
+    fn global_stride(&self) -> u64 {
+        self.large_number
+            .checked_div(self.global_tickets)
+            .unwrap_or(0)
+    }
+
+    // client_join(): start the job remain past the global pass, so a late
+    // joiner competes from now on instead of from time zero
+    fn join(&mut self, mut job: Job) {
+        job.pass = self.global_pass.wrapping_add_signed(job.remain);
+        self.global_tickets += job.tickets as u64;
+        self.queue.push(job);
+    }
+
+    // client_leave(): take the job off the queue, remembering how far
+    // ahead of (or behind) the global pass it was
+    fn leave(&mut self, id: u32) -> Option<Job> {
+        // an idle group already left, with its remain recorded
+        if let Some(i) = self.idle.iter().position(|job| job.id == id) {
+            return Some(self.idle.swap_remove(i));
+        }
+
+        let mut jobs = std::mem::take(&mut self.queue).into_vec();
+        let i = jobs.iter().position(|job| job.id == id);
+        let job = i.map(|i| jobs.swap_remove(i));
+        self.queue = jobs.into();
+
+        let mut job = job?;
+        job.remain = pass_diff(job.pass, self.global_pass);
+        self.global_tickets -= job.tickets as u64;
+        Some(job)
+    }
+
     fn add_job(&mut self, id: u32, tickets: u32) {
         let job = Job::new(id, tickets, self.large_number);
         println!(
-            "Adding Job {}: tickets={}, stride={}",
+            "Adding Job {}: tickets={}, stride={}, pass={}",
+            id,
+            tickets,
+            job.stride,
+            self.global_pass.wrapping_add_signed(job.remain)
+        );
+        self.join(job);
+    }
+
+    // removing a group removes everything in it
+    fn remove_job(&mut self, id: u32) -> Option<Job> {
+        let job = self.leave(id)?;
+        println!("Removing Job {} (remain={})", id, job.remain);
+        self.groups.remove(&id);
+        Some(job)
+    }
+
+    // Add a group funded with tickets from this scheduler. Its members
+    // are added through group_mut() and hold tickets in the group's own
+    // currency, so however many the group issues, together they are
+    // worth exactly its funding here. Groups nest. Ids of jobs and
+    // groups share one namespace per scheduler, and the job ids
+    // schedule() returns should be unique across the whole tree.
+    fn add_group(&mut self, id: u32, tickets: u32) {
+        let group = StrideScheduler::new(self.quantum).with_large_number(self.large_number);
+        let job = Job::new(id, tickets, self.large_number);
+        println!(
+            "Adding Group {}: tickets={}, stride={}",
             id, tickets, job.stride
         );
-        self.queue.push(job);
+        self.groups.insert(id, group);
+        // it has no members yet
+        self.idle.push(job);
+    }
+
+    fn group_mut(&mut self, id: u32) -> Option<&mut StrideScheduler> {
+        self.groups.get_mut(&id)
+    }
+
+    // anything to run here or in a subgroup
+    fn runnable(&self) -> bool {
+        self.queue
+            .iter()
+            .any(|job| !self.groups.contains_key(&job.id))
+            || self.groups.values().any(StrideScheduler::runnable)
+    }
+
+    // A group with nothing to run leaves the queue, so its tickets stop
+    // counting toward the global stride, and rejoins when it has work
+    // again. This is Waldspurger's currency deactivation, done lazily
+    // before each decision since members are added through group_mut().
+    fn activate_groups(&mut self) {
+        let empty: Vec<u32> = self
+            .queue
+            .iter()
+            .map(|job| job.id)
+            .filter(|id| self.groups.get(id).is_some_and(|group| !group.runnable()))
+            .collect();
+        for id in empty {
+            let job = self.leave(id).expect("queued group");
+            self.idle.push(job);
+        }
+
+        let (ready, idle) = std::mem::take(&mut self.idle)
+            .into_iter()
+            .partition(|job| self.groups[&job.id].runnable());
+        self.idle = idle;
+        for job in ready {
+            self.join(job);
+        }
+    }
+
+    // tickets held by jobs and groups with something to run; what
+    // global_tickets will be once activate_groups() next runs
+    fn active_tickets(&self) -> u64 {
+        self.queue
+            .iter()
+            .chain(&self.idle)
+            .filter(|job| {
+                self.groups
+                    .get(&job.id)
+                    .is_none_or(StrideScheduler::runnable)
+            })
+            .map(|job| job.tickets as u64)
+            .sum()
+    }
+
+    // What one ticket of a group's currency is worth in this scheduler's:
+    // the group's funding over the tickets its active members hold. None
+    // if it is not a group here or has no active members.
+    fn exchange_rate(&self, group: u32) -> Option<f64> {
+        let issued = self.groups.get(&group)?.active_tickets();
+        let funding = self
+            .queue
+            .iter()
+            .chain(&self.idle)
+            .find(|job| job.id == group)?
+            .tickets;
+        (issued > 0).then(|| funding as f64 / issued as f64)
+    }
+
+    // a job's tickets converted into this scheduler's currency, through
+    // the exchange rates of the groups it is nested in
+    fn base_tickets(&self, id: u32) -> Option<f64> {
+        let job = self
+            .queue
+            .iter()
+            .find(|job| job.id == id && !self.groups.contains_key(&id));
+        if let Some(job) = job {
+            return Some(job.tickets as f64);
+        }
+        self.groups
+            .iter()
+            .find_map(|(&gid, group)| Some(group.base_tickets(id)? * self.exchange_rate(gid)?))
+    }
+
+    // client_modify(): leave, scale the remaining pass by the change in
+    // stride, and rejoin; false if there is no such job
+    fn set_tickets(&mut self, id: u32, tickets: u32) -> bool {
+        let Some(old) = self.leave(id) else {
+            return false;
+        };
+        let mut job = Job::new(id, tickets, self.large_number);
+        job.frac = old.frac;
+        job.remain = (old.remain as i128 * job.stride as i128)
+            .checked_div(old.stride as i128)
+            .map_or(job.stride as i64, |remain| remain as i64);
+        println!(
+            "Job {}: tickets {} -> {}, stride {} -> {}",
+            id, old.tickets, tickets, old.stride, job.stride
+        );
+        self.join(job);
+        true
     }
 
-    // stride scheduling decision code
+    // stride scheduling decision code: every job uses its whole quantum
     fn schedule(&mut self) -> Option<u32> {
+        let quantum = self.quantum;
+        self.schedule_with(|_| quantum)
+    }
+
+    // Like schedule(), but run(id) runs the picked job and returns how
+    // long it actually ran, e.g. less than the quantum if it blocked.
+    // Pass advances in proportion, so a job that yields early is charged
+    // only for the time it used and keeps its share of the cpu.
+    //
+    // Picking a group picks again among its members, recursively, and
+    // the time used is charged at every level on the way down.
+    fn schedule_with(&mut self, mut run: impl FnMut(u32) -> u32) -> Option<u32> {
+        self.dispatch(&mut run)
+    }
+
+    // schedule_with() without the generic, so groups can recurse
+    fn dispatch(&mut self, run: &mut dyn FnMut(u32) -> u32) -> Option<u32> {
+        self.activate_groups();
         if self.queue.is_empty() {
             return None;
         }
 
         // curr = remove_min(queue) // pick client with min pass
         let mut curr = self.queue.pop()?;
+        let kind = if self.groups.contains_key(&curr.id) {
+            "Group"
+        } else {
+            "Job"
+        };
         println!(
-            "Scheduled Job {} (pass={}, stride={})",
-            curr.id, curr.pass, curr.stride
+            "Scheduled {} {} (pass={}, stride={})",
+            kind, curr.id, curr.pass, curr.stride
         );
 
-        // schedule(curr); //run for quantum
-        // (In real system, this would run the job for quantum time)
+        // elapsed = schedule(curr, quantum); // run for up to a quantum
+        let (picked, used) = match self.groups.get_mut(&curr.id) {
+            Some(group) => {
+                let mut used = 0;
+                let picked = group.dispatch(&mut |id| {
+                    used = run(id);
+                    used
+                });
+                (picked.expect("active group has members"), used)
+            }
+            None => (curr.id, run(curr.id)),
+        };
 
-        // curr->pass += curr->stride; // update pass using stride
-        curr.pass += curr.stride;
-        println!("  Updated Job {} pass to {}", curr.id, curr.pass);
+        // curr->pass += curr->stride * elapsed / quantum;
+        advance(
+            &mut curr.pass,
+            &mut curr.frac,
+            curr.stride,
+            used,
+            self.quantum,
+        );
+        let global_stride = self.global_stride();
+        advance(
+            &mut self.global_pass,
+            &mut self.global_frac,
+            global_stride,
+            used,
+            self.quantum,
+        );
+        println!(
+            "  {} {} ran {}/{}ms, pass now {}",
+            kind, curr.id, used, self.quantum, curr.pass
+        );
 
         // insert(queue, curr); //return curr to queue
-        self.queue.push(curr.clone());
+        self.queue.push(curr);
+
+        Some(picked)
+    }
 
-        Some(curr.id)
+    // the ids picked by the next rounds calls to schedule(), e.g. for
+    // comparing against a golden trace
+    fn schedule_rounds(&mut self, rounds: usize) -> Vec<u32> {
+        (0..rounds).map_while(|_| self.schedule()).collect()
     }
 
     fn show_queue(&self) {
         println!("Current queue state:");
         let mut jobs: Vec<_> = self.queue.iter().collect();
-        jobs.sort_by_key(|job| job.pass);
+        jobs.sort_by(|a, b| b.cmp(a)); // in the order they would run
         for job in jobs {
-            println!("  Job {}: pass={}, stride={}", job.id, job.pass, job.stride);
+            let kind = if self.groups.contains_key(&job.id) {
+                "Group"
+            } else {
+                "Job"
+            };
+            println!(
+                "  {} {}: pass={}, stride={}",
+                kind, job.id, job.pass, job.stride
+            );
         }
     }
 }
 
 fn main() {
-    let mut scheduler = StrideScheduler::new(100); //100ms quantum
+    let mut scheduler = StrideScheduler::new(100).with_large_number(10000); //100ms quantum
 
     // add jobs with different ticket allocations
     scheduler.add_job(1, 100); // Job 1: 100 tickets, stride = 100
@@ -115,15 +403,55 @@ fn main() {
     for round in 1..=10 {
         println!("\n--- Round {} ---", round);
         if let Some(job_id) = scheduler.schedule() {
-            println!("Job {} runs for quantum", job_id);
+            println!("Job {} runs for {}ms quantum", job_id, scheduler.quantum);
         }
 
         scheduler.show_queue();
     }
 
+    // jobs can come and go mid-run
+    println!("\nJob 4 joins, job 3 gets more tickets, job 2 leaves:");
+    scheduler.add_job(4, 100);
+    scheduler.set_tickets(3, 50);
+    scheduler.remove_job(2);
+    for _ in 0..5 {
+        scheduler.schedule();
+    }
+
+    // job 4 blocks for I/O after 25ms of every quantum, so it is charged a
+    // quarter stride per turn and gets picked about four times as often
+    println!("\nJob 4 blocks early:");
+    for _ in 0..5 {
+        scheduler.schedule_with(|id| if id == 4 { 25 } else { 100 });
+    }
+
     // show final pass values
-    println!("\nFinal pass values after 10 rounds:");
+    println!("\nFinal pass values:");
     scheduler.show_queue();
+    let schedule = scheduler.schedule_rounds(8);
+    println!("\nNext 8 rounds: {:?}", schedule);
+
+    // two teams share a machine 1:1; team A issues 3 tickets of its own
+    // currency and team B 300, which changes nothing between the teams
+    println!("\nGroups with their own ticket currencies:");
+    let mut machine = StrideScheduler::new(100);
+    machine.add_group(10, 100);
+    machine.add_group(20, 100);
+    let team_a = machine.group_mut(10).unwrap();
+    team_a.add_job(1, 1);
+    team_a.add_job(2, 2);
+    let team_b = machine.group_mut(20).unwrap();
+    team_b.add_job(3, 100);
+    team_b.add_job(4, 200);
+    for id in 1..=4 {
+        println!(
+            "Job {} is worth {:.1} base tickets",
+            id,
+            machine.base_tickets(id).unwrap()
+        );
+    }
+    let schedule = machine.schedule_rounds(12);
+    println!("\nFirst 12 rounds: {:?}", schedule);
 }
 
 #[cfg(test)]
@@ -169,4 +497,368 @@ mod tests {
             ratio
         );
     }
+
+    fn count(schedule: &[u32], id: u32) -> usize {
+        schedule.iter().filter(|&&job| job == id).count()
+    }
+
+    #[test]
+    fn test_late_join_gets_fair_share() {
+        let mut scheduler = StrideScheduler::new(100);
+        scheduler.add_job(1, 100);
+        scheduler.add_job(2, 100);
+        scheduler.schedule_rounds(1000);
+
+        // with pass 0, job 3 would run ~500 times in a row to catch up
+        scheduler.add_job(3, 100);
+        let schedule = scheduler.schedule_rounds(300);
+        let longest = schedule
+            .chunk_by(|a, b| a == b)
+            .map(|run| run.len())
+            .max()
+            .unwrap();
+        assert!(longest <= 2, "job ran {} quanta in a row", longest);
+        for id in 1..=3 {
+            assert!(count(&schedule, id).abs_diff(100) <= 2);
+        }
+    }
+
+    #[test]
+    fn test_remove_job() {
+        let mut scheduler = StrideScheduler::new(100);
+        scheduler.add_job(1, 100);
+        scheduler.add_job(2, 50);
+        scheduler.schedule_rounds(30);
+
+        let job = scheduler.remove_job(1).unwrap();
+        assert_eq!(job.id, 1);
+        assert!(scheduler.remove_job(1).is_none());
+        assert_eq!(scheduler.global_tickets, 50);
+        assert!(scheduler.schedule_rounds(10).iter().all(|&id| id == 2));
+
+        // rejoining keeps the pass it had left to run, not a fresh start
+        let remain = job.remain;
+        scheduler.join(job);
+        let pass = scheduler.queue.iter().find(|job| job.id == 1).unwrap().pass;
+        assert_eq!(pass, scheduler.global_pass.wrapping_add_signed(remain));
+    }
+
+    #[test]
+    fn test_set_tickets() {
+        let mut scheduler = StrideScheduler::new(100);
+        scheduler.add_job(1, 100);
+        scheduler.add_job(2, 100);
+        scheduler.schedule_rounds(100);
+
+        assert!(scheduler.set_tickets(2, 300));
+        assert!(!scheduler.set_tickets(9, 10));
+        assert_eq!(scheduler.global_tickets, 400);
+        let schedule = scheduler.schedule_rounds(400);
+        assert!(count(&schedule, 1).abs_diff(100) <= 2);
+        assert!(count(&schedule, 2).abs_diff(300) <= 2);
+    }
+
+    #[test]
+    fn test_partial_quantum_charges_partial_stride() {
+        let mut scheduler = StrideScheduler::new(100);
+        scheduler.add_job(1, 100); // stride 100, pass 100
+        scheduler.add_job(2, 30); // stride 333, pass 333
+
+        assert_eq!(scheduler.schedule_with(|_| 25), Some(1));
+        let job = scheduler.queue.peek().unwrap();
+        assert_eq!((job.id, job.pass), (1, 125));
+
+        // three thirds of a quantum charge exactly one stride
+        scheduler.remove_job(1);
+        let pass = scheduler.queue.peek().unwrap().pass;
+        scheduler.quantum = 3;
+        for _ in 0..3 {
+            scheduler.schedule_with(|_| 1);
+        }
+        assert_eq!(scheduler.queue.peek().unwrap().pass, pass + 333);
+    }
+
+    #[test]
+    fn test_early_yield_not_penalized() {
+        let mut scheduler = StrideScheduler::new(100);
+        scheduler.add_job(1, 100);
+        scheduler.add_job(2, 100);
+
+        // job 2 blocks after a fifth of its quantum every time
+        let mut cpu = [0u32; 3];
+        let mut turns = [0u32; 3];
+        for _ in 0..600 {
+            scheduler.schedule_with(|id| {
+                let used = if id == 2 { 20 } else { 100 };
+                cpu[id as usize] += used;
+                turns[id as usize] += 1;
+                used
+            });
+        }
+        // equal tickets, equal cpu time, however it is sliced
+        assert!(cpu[1].abs_diff(cpu[2]) <= 100, "cpu time {:?}", cpu);
+        assert!(turns[2].abs_diff(5 * turns[1]) <= 5, "turns {:?}", turns);
+    }
+
+    // Skip ahead as if schedule() had been called quanta times, without
+    // running them: every job whose pass is below some virtual time t has
+    // run once per stride up to t. Find the last t that fits in quanta
+    // and give the leftover picks to jobs sitting exactly at t.
+    fn fast_forward(scheduler: &mut StrideScheduler, quanta: u64) {
+        let base = scheduler.global_pass;
+        let mut jobs = std::mem::take(&mut scheduler.queue).into_vec();
+        let offset = |job: &Job| pass_diff(job.pass, base) as i128;
+        let runs = |job: &Job, t: i128| -> i128 {
+            let ahead = t - offset(job);
+            if ahead <= 0 {
+                0
+            } else {
+                (ahead + job.stride as i128 - 1) / job.stride as i128
+            }
+        };
+        let total = |jobs: &[Job], t: i128| jobs.iter().map(|job| runs(job, t)).sum::<i128>();
+
+        let (mut lo, mut hi) = (i128::MIN / 4, i128::MAX / 4);
+        while lo < hi {
+            let mid = lo + (hi - lo + 1) / 2;
+            if total(&jobs, mid) <= quanta as i128 {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        let mut left = quanta as i128 - total(&jobs, lo);
+        jobs.sort_by_key(|job| job.id);
+        for job in &mut jobs {
+            let mut n = runs(job, lo);
+            if left > 0 && offset(job) + n * job.stride as i128 == lo {
+                n += 1;
+                left -= 1;
+            }
+            job.pass = job.pass.wrapping_add((n * job.stride as i128) as u64);
+        }
+        scheduler.queue = jobs.into();
+        let global = quanta as u128 * scheduler.global_stride() as u128;
+        scheduler.global_pass = base.wrapping_add(global as u64);
+    }
+
+    #[test]
+    fn test_fast_forward_matches_schedule() {
+        let mut slow = StrideScheduler::new(100);
+        let mut fast = StrideScheduler::new(100);
+        for (id, tickets) in [(1, 7), (2, 100), (3, 3)] {
+            slow.add_job(id, tickets);
+            fast.add_job(id, tickets);
+        }
+        slow.schedule_rounds(5000);
+        fast_forward(&mut fast, 5000);
+        let passes = |s: &StrideScheduler| {
+            let mut passes: Vec<_> = s.queue.iter().map(|job| (job.id, job.pass)).collect();
+            passes.sort();
+            passes
+        };
+        assert_eq!(passes(&fast), passes(&slow));
+        assert_eq!(fast.global_pass, slow.global_pass);
+    }
+
+    // The same check at the billions test's 1:100000 ratio, starting one
+    // large stride below u64::MAX so the passes wrap partway through
+    #[test]
+    fn test_fast_forward_matches_schedule_across_the_wrap() {
+        let start = |s: &mut StrideScheduler| {
+            s.global_pass = u64::MAX - (1 << 50);
+            s.add_job(1, 1);
+            s.add_job(2, 100_000);
+        };
+        let mut slow = StrideScheduler::new(100).with_large_number(1 << 50);
+        let mut fast = StrideScheduler::new(100).with_large_number(1 << 50);
+        start(&mut slow);
+        start(&mut fast);
+        slow.schedule_rounds(300_000);
+        fast_forward(&mut fast, 300_000);
+        assert!(slow.global_pass < 1 << 60, "never wrapped");
+        let passes = |s: &StrideScheduler| {
+            let mut passes: Vec<_> = s.queue.iter().map(|job| (job.id, job.pass)).collect();
+            passes.sort();
+            passes
+        };
+        assert_eq!(passes(&fast), passes(&slow));
+        assert_eq!(fast.global_pass, slow.global_pass);
+    }
+
+    // Start the global pass five strides below u64::MAX and let
+    // schedule() itself carry every pass across the wrap. Only
+    // differences between passes matter, so the picks must be exactly
+    // those of a scheduler that started at zero, ties included.
+    #[test]
+    fn test_schedule_across_the_wrap() {
+        let jobs = [(1, 100), (2, 50), (3, 50), (4, 25)];
+        let mut fresh = StrideScheduler::new(100);
+        let mut wrapping = StrideScheduler::new(100);
+        wrapping.global_pass = u64::MAX - 5 * 100;
+        for (id, tickets) in jobs {
+            fresh.add_job(id, tickets);
+            wrapping.add_job(id, tickets);
+        }
+        assert!(wrapping.queue.iter().all(|job| job.pass > u64::MAX - 500));
+
+        let schedule = wrapping.schedule_rounds(900);
+        assert!(wrapping.queue.iter().all(|job| job.pass < 100_000));
+        assert_eq!(schedule, fresh.schedule_rounds(900));
+        // ties at equal pass still go to the lower id: all four jobs sit
+        // at the same pass before round 6, and job 1 wins it
+        assert_eq!(schedule[..8], [1, 1, 2, 3, 1, 1, 2, 3]);
+        // shares 4:2:2:1
+        for (id, tickets) in jobs {
+            assert!(count(&schedule, id).abs_diff(tickets as usize * 4) <= 1);
+        }
+    }
+
+    // Five billion quanta at a 1:100000 ticket ratio with a 2^50
+    // large_number move the passes past u64::MAX several times. Those
+    // quanta are skipped analytically by fast_forward, not run; only the
+    // million that follow go through schedule(), and they must still
+    // hand out exact shares.
+    #[test]
+    fn test_billions_of_quanta_wrap_safely() {
+        let mut scheduler = StrideScheduler::new(100).with_large_number(1 << 50);
+        scheduler.add_job(1, 1);
+        scheduler.add_job(2, 100_000);
+        fast_forward(&mut scheduler, 5_000_000_000);
+        let wraps = (5_000_000_000u128 * scheduler.global_stride() as u128) >> 64;
+        assert!(wraps >= 3, "only wrapped {} times", wraps);
+
+        let schedule = scheduler.schedule_rounds(1_000_000);
+        assert!(count(&schedule, 1).abs_diff(10) <= 1);
+        // the rare job stays spaced a full period apart
+        let turns: Vec<_> = (0..schedule.len()).filter(|&i| schedule[i] == 1).collect();
+        for pair in turns.windows(2) {
+            assert!((pair[1] - pair[0]).abs_diff(100_001) <= 1);
+        }
+        // and the queued passes are still within one large stride of each
+        // other, nowhere near the 2^63 the wrapping compare relies on
+        let passes: Vec<_> = scheduler.queue.iter().map(|job| job.pass).collect();
+        assert!(pass_diff(passes[0], passes[1]).unsigned_abs() <= 1 << 50);
+    }
+
+    #[test]
+    fn test_equal_pass_goes_to_lower_id() {
+        let mut scheduler = StrideScheduler::new(100);
+        // added highest id first, so insertion order would differ
+        for id in [5, 3, 9, 1] {
+            scheduler.add_job(id, 10);
+        }
+        assert_eq!(scheduler.schedule_rounds(8), vec![1, 3, 5, 9, 1, 3, 5, 9]);
+        assert!(StrideScheduler::new(100).schedule_rounds(3).is_empty());
+    }
+
+    // the demo in main(), pinned
+    #[test]
+    fn test_golden_trace() {
+        let mut scheduler = StrideScheduler::new(100);
+        scheduler.add_job(1, 100);
+        scheduler.add_job(2, 50);
+        scheduler.add_job(3, 25);
+        assert_eq!(
+            scheduler.schedule_rounds(14),
+            vec![1, 1, 2, 1, 1, 2, 3, 1, 1, 2, 1, 1, 2, 3]
+        );
+    }
+
+    // counts of each job over rounds, against its share of base tickets
+    fn assert_shares(scheduler: &mut StrideScheduler, ids: &[u32], rounds: usize, slack: usize) {
+        let total: f64 = ids
+            .iter()
+            .map(|&id| scheduler.base_tickets(id).unwrap())
+            .sum();
+        let expected: Vec<_> = ids
+            .iter()
+            .map(|&id| {
+                (scheduler.base_tickets(id).unwrap() / total * rounds as f64).round() as usize
+            })
+            .collect();
+        let schedule = scheduler.schedule_rounds(rounds);
+        let counts: Vec<_> = ids.iter().map(|&id| count(&schedule, id)).collect();
+        for (got, want) in counts.iter().zip(&expected) {
+            assert!(
+                got.abs_diff(*want) <= slack,
+                "counts {:?}, expected {:?}",
+                counts,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_nested_group_shares() {
+        // base: job 1 (100) and group 10 (300)
+        //   group 10: job 2 (1) and group 20 (3), so a ticket is worth 75
+        //     group 20: job 3 (50) and job 4 (150), a ticket worth 3/200 of
+        //     group 10's, or 225/200 base tickets
+        // 10000 / 150 would truncate the stride by 1%, so use finer strides
+        let mut scheduler = StrideScheduler::new(100).with_large_number(1 << 40);
+        scheduler.add_job(1, 100);
+        scheduler.add_group(10, 300);
+        let g10 = scheduler.group_mut(10).unwrap();
+        g10.add_job(2, 1);
+        g10.add_group(20, 3);
+        let g20 = g10.group_mut(20).unwrap();
+        g20.add_job(3, 50);
+        g20.add_job(4, 150);
+
+        assert_eq!(scheduler.exchange_rate(10), Some(75.0));
+        assert_eq!(
+            scheduler.group_mut(10).unwrap().exchange_rate(20),
+            Some(0.015) // 3 / 200, in group 10's currency
+        );
+        assert_eq!(scheduler.base_tickets(2), Some(75.0));
+        assert_eq!(scheduler.base_tickets(4), Some(168.75));
+        assert_eq!(scheduler.exchange_rate(1), None);
+
+        // shares 1/4, 3/16, 9/64, 27/64
+        assert_shares(&mut scheduler, &[1, 2, 3, 4], 6400, 4);
+    }
+
+    #[test]
+    fn test_inflation_stays_inside_group() {
+        let mut scheduler = StrideScheduler::new(100);
+        scheduler.add_group(10, 100);
+        scheduler.add_group(20, 100);
+        let a = scheduler.group_mut(10).unwrap();
+        a.add_job(1, 1);
+        a.add_job(2, 1);
+        scheduler.group_mut(20).unwrap().add_job(3, 1);
+        assert_shares(&mut scheduler, &[1, 2, 3], 4000, 2);
+
+        // group 10 prints a thousand times more tickets for job 1: its
+        // currency is devalued, but it still only has its 100 to spend
+        scheduler.group_mut(10).unwrap().set_tickets(1, 1000);
+        assert_eq!(scheduler.exchange_rate(10), Some(100.0 / 1001.0));
+        assert_eq!(scheduler.base_tickets(3), Some(100.0));
+        let schedule = scheduler.schedule_rounds(4000);
+        assert!(count(&schedule, 3).abs_diff(2000) <= 2);
+        assert!(count(&schedule, 1).abs_diff(1998) <= 2);
+    }
+
+    #[test]
+    fn test_empty_group_is_inactive() {
+        let mut scheduler = StrideScheduler::new(100);
+        scheduler.add_job(1, 100);
+        scheduler.add_group(10, 100);
+        assert_eq!(scheduler.schedule_rounds(1000), vec![1; 1000]);
+        assert_eq!(scheduler.global_tickets, 100);
+
+        // once it has work it joins at the global pass like any late
+        // joiner, instead of cashing in the time it sat idle
+        scheduler.group_mut(10).unwrap().add_job(2, 5);
+        let schedule = scheduler.schedule_rounds(1000);
+        assert!(schedule.chunk_by(|a, b| a == b).all(|run| run.len() <= 2));
+        assert!(count(&schedule, 2).abs_diff(500) <= 2);
+
+        // and goes idle again when its last member leaves
+        scheduler.group_mut(10).unwrap().remove_job(2);
+        assert_eq!(scheduler.schedule_rounds(10), vec![1; 10]);
+        assert!(scheduler.remove_job(10).is_some());
+        assert!(scheduler.group_mut(10).is_none());
+    }
 }